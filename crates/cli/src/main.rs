@@ -1,24 +1,226 @@
-use clap::Parser;
-use core::{parse, run_query};
+use clap::{Parser, ValueEnum};
+use core::{
+    parse, parse_with_parameters, run_query_with_config, run_query_with_trace, EngineConfig,
+    BENCHMARK_QUERIES,
+};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing_subscriber::fmt::format::FmtSpan;
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[allow(clippy::struct_excessive_bools)]
 struct Args {
     /// SQL query to run
+    #[arg(short, long, required_unless_present = "bench")]
+    sql: Option<String>,
+
+    /// Value for a `$1`, `$2`... parameter in the query, in order. Parsed as JSON, or
+    /// used as text if it isn't valid JSON
+    #[arg(short = 'a', long = "parameter")]
+    parameters: Vec<String>,
+
+    /// Run the built in benchmark queries and print their timings
+    #[arg(short, long)]
+    bench: bool,
+
+    /// Log parsing and each executed operator to stderr
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Print the plan annotated with per-operator statistics to stderr
+    #[arg(short, long)]
+    profile: bool,
+
+    /// Print each operator's columns and first few rows to stderr as it runs
     #[arg(short, long)]
-    sql: String,
+    trace: bool,
+
+    /// Re-run the query on an interval such as `2s` or `500ms`, redrawing the result
+    #[arg(short, long, value_parser = parse_interval)]
+    watch: Option<Duration>,
+
+    /// Write the result to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Format of the result, taken from the output file's extension if not given
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Number of threads to split big scans, filters and joins across, defaults to one per core
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// Bytes of rows a hash join can hold in memory before it spills to disk
+    #[arg(long)]
+    join_memory_budget: Option<usize>,
+}
+
+impl Args {
+    fn engine_config(&self) -> EngineConfig {
+        let default = EngineConfig::default();
+
+        EngineConfig {
+            threads: self.threads.unwrap_or(default.threads),
+            join_memory_budget: self
+                .join_memory_budget
+                .unwrap_or(default.join_memory_budget),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    fn from_path(path: &std::path::Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
-    let query = parse(&args.sql).unwrap();
-    match run_query(&query) {
+    if args.verbose {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_span_events(FmtSpan::CLOSE)
+            .init();
+    }
+
+    if args.bench {
+        run_benchmarks(&args.engine_config());
+        return;
+    }
+
+    let parameters: Vec<serde_json::Value> = args
+        .parameters
+        .iter()
+        .map(|parameter| {
+            serde_json::from_str(parameter).unwrap_or_else(|_| parameter.as_str().into())
+        })
+        .collect();
+
+    // reported the same way as errors running the query
+    let query = match parse_with_parameters(args.sql.as_deref().unwrap(), &parameters) {
+        Ok(query) => query,
+        Err(err) => {
+            println!("{err:?}");
+            return;
+        }
+    };
+
+    match args.watch {
+        Some(interval) => loop {
+            // clear the terminal and move the cursor back to the top
+            print!("\x1b[2J\x1b[H");
+            run_and_print(&query, &args);
+            std::thread::sleep(interval);
+        },
+        None => run_and_print(&query, &args),
+    }
+}
+
+fn run_and_print(query: &core::Query, args: &Args) {
+    // tracing keeps every operator's rows around, so only do it when asked
+    let config = args.engine_config();
+    let result = if args.trace {
+        run_query_with_trace(query, &config, &mut print_step)
+    } else {
+        run_query_with_config(query, &config)
+    };
+
+    match result {
         Ok(result) => {
-            println!("{}", result.to_json());
+            if args.profile {
+                eprint!("{}", result.profile);
+            }
+            if let Err(err) = write_result(&result, args) {
+                eprintln!("could not write result: {err}");
+            }
         }
         Err(err) => {
-            println!("{err:?}",);
+            println!("{err:?}");
+        }
+    }
+}
+
+fn write_result(result: &core::QueryStep, args: &Args) -> std::io::Result<()> {
+    let format = args
+        .format
+        .or_else(|| args.output.as_deref().and_then(Format::from_path))
+        .unwrap_or(Format::Json);
+
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    match format {
+        Format::Json => writeln!(writer, "{}", result.to_json())?,
+        Format::Csv => result.write_csv(&mut writer)?,
+    }
+    writer.flush()
+}
+
+// `500ms`, `2s` or `1m`
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (amount, unit) = interval.split_at(split);
+
+    let amount = amount
+        .parse::<u64>()
+        .map_err(|_| format!("expected an interval like 2s, found {interval}"))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "s" | "" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        _ => Err(format!("unknown interval unit {unit}, expected ms, s or m")),
+    }
+}
+
+fn run_benchmarks(config: &EngineConfig) {
+    for (name, sql) in BENCHMARK_QUERIES {
+        let query = parse(sql).unwrap();
+        let started = Instant::now();
+        match run_query_with_config(&query, config) {
+            Ok(result) => println!(
+                "{name}: {:?}, {} rows processed",
+                started.elapsed(),
+                result.cost.rows_processed
+            ),
+            Err(err) => println!("{name}: {err:?}"),
+        }
+    }
+}
+
+// how many rows of each intermediate result `--trace` shows
+const TRACE_ROWS: usize = 5;
+
+fn print_step(step: &core::QueryStep) {
+    eprintln!("== {} ({} rows)", step.profile.operator, step.rows.len());
+    eprintln!("columns: {}", step.schema.output_names().join(", "));
+
+    if let serde_json::Value::Array(rows) = step.to_json() {
+        for row in rows.iter().take(TRACE_ROWS) {
+            eprintln!("{row}");
+        }
+        if rows.len() > TRACE_ROWS {
+            eprintln!("...");
         }
     }
+    eprintln!();
 }
@@ -2,5 +2,7 @@ mod parser;
 mod query;
 mod types;
 
-pub use parser::parse;
-pub use query::run_query;
+pub use parser::{parse, parse_with_parameters};
+pub use query::tpch::BENCHMARK_QUERIES;
+pub use query::{run_query, run_query_with_config, run_query_with_trace, EngineConfig};
+pub use types::{Query, QueryStep};
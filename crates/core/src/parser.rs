@@ -1,12 +1,16 @@
 use sqlparser::ast::{
     self, FunctionArg, FunctionArgExpr, FunctionArgumentList, LimitClause, OrderByKind,
 };
-use sqlparser::dialect::AnsiDialect;
-use sqlparser::parser::Parser;
+use sqlparser::dialect::Dialect;
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::Token;
 
+use crate::query;
 use crate::types::{
-    AggregateFunctionName, Column, Expr, Filter, From, FunctionName, Join, JoinOn, JoinType, Limit,
-    Op, Order, OrderBy, OrderByExpr, Project, Query, TableAlias, TableName,
+    AggregateFunctionName, Column, CrossJoin, DataType, Describe, Distinct, Explain, Expr, Filter,
+    From, FunctionName, GroupBy, Join, JoinOn, JoinType, Limit, NestedLoopJoin, Op, Order, OrderBy,
+    OrderByExpr, Project, Quantifier, Query, Sample, ScalarFunctionName, TableAlias,
+    TableFunctionCall, TableFunctionName, TableName, Union,
 };
 
 #[derive(Debug)]
@@ -14,10 +18,8 @@ pub enum ParseError {
     NoStatements,
     OnlyQueryIsSupported,
     WithNotSupported,
-    OffsetNotSupported,
     OffsetCommaLimitNotSupported,
     LimitByNotSupported,
-    LimitMustBeInt,
     FetchNotSupported,
     LocksNotSupported,
     ForClauseNotSupported,
@@ -25,6 +27,7 @@ pub enum ParseError {
     FormatNotSupported,
     PipeOperatorsNotSupported,
     OnlySelectIsSupported,
+    SetQuantifierNotSupported,
     IntoNotSupported,
     EmptyFromNotSupported,
     DistinctNotSupported,
@@ -32,17 +35,32 @@ pub enum ParseError {
     EmptyObjectName,
     UnknownExprPart { expr: String },
     GroupByNotSupported,
+    GroupByRequiresFields,
+    HavingRequiresGroupBy,
+    CubeTooLarge { items: usize },
+    GroupByPositionNotInSelectList { position: serde_json::Value },
     SortByNotSupported,
     ExpectedIdent { found: String },
     ExpectedTwoIdents,
     UnsupportedProjectionField,
     TableAliasColumnsNotSupported,
+    UnknownTableFunction { name: String },
+    UnnestOffsetNotSupported,
+    ArraySliceNotSupported,
+    LikeAnyNotSupported,
+    InvalidEscapeCharacter { found: String },
     Join(JoinParseError),
     OrderBy(OrderByParseError),
     Function(FunctionParseError),
-    ExpectedValue(ast::Expr),
+    ExpectedValue(Box<ast::Expr>),
     SerdeJsonError(String, serde_json::Error),
     UnknownOperator,
+    CastNotSupported { data_type: String },
+    UnsupportedParameter { name: String },
+    ParameterNotGiven { index: usize },
+    TableSampleNotSupported,
+    InvalidSamplePercent { percent: String },
+    InvalidSampleSeed { seed: String },
 }
 
 #[derive(Debug)]
@@ -55,20 +73,24 @@ pub enum OrderByParseError {
 pub enum JoinParseError {
     UnsupportedJoinOperator,
     UnsupportedJoinConstraint,
+    UsingMultipleColumns,
 }
 
 #[derive(Debug)]
 pub enum FunctionParseError {
     OdbcSyntaxNotSupported,
-    DuplicateTreatmentNotSupported,
     ClausesNotSupported,
     ParametersNotSupported,
     WithinGroupNotSupported,
+    WithinGroupRequired,
     FilterNotSupported,
     NullTreatmentNotSupported,
     OverNotSupported,
     EmptyObjectName,
     SubQueryNotSupported,
+    DistinctNotSupported,
+    // `*` as an argument only makes sense for `count(*)`
+    WildcardOnlyInCount,
     UnknownFunctionName { ident: String },
 }
 
@@ -78,8 +100,48 @@ impl std::convert::From<JoinParseError> for ParseError {
     }
 }
 
+// ANSI SQL, except the JSON access and array containment operators bind
+// tighter than comparisons like they do in Postgres, so
+// `meta ->> 'country' = 'UK'` compares the extracted value rather than
+// extracting `'country' = 'UK'`
+#[derive(Debug)]
+struct SqlDialect;
+
+// between `+`/`-` and comparisons on the default precedence scale
+const OTHER_OPERATOR_PRECEDENCE: u8 = 25;
+
+impl Dialect for SqlDialect {
+    fn is_identifier_start(&self, ch: char) -> bool {
+        ch.is_ascii_lowercase() || ch.is_ascii_uppercase()
+    }
+
+    fn is_identifier_part(&self, ch: char) -> bool {
+        ch.is_ascii_lowercase() || ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_'
+    }
+
+    // `interval '7 days'` like postgres, rather than only `interval '7' day`
+    fn require_interval_qualifier(&self) -> bool {
+        false
+    }
+
+    fn supports_group_by_expr(&self) -> bool {
+        true
+    }
+
+    fn get_next_precedence(&self, parser: &Parser) -> Option<Result<u8, ParserError>> {
+        match parser.peek_token().token {
+            Token::Arrow | Token::LongArrow | Token::AtArrow | Token::ArrowAt => {
+                Some(Ok(OTHER_OPERATOR_PRECEDENCE))
+            }
+            _ => None,
+        }
+    }
+}
+
 pub fn parse(sql: &str) -> Result<Query, ParseError> {
-    let dialect = AnsiDialect {}; // or AnsiDialect
+    let _span = tracing::info_span!("parse").entered();
+
+    let dialect = SqlDialect;
 
     let ast = Parser::parse_sql(&dialect, sql).unwrap();
 
@@ -90,9 +152,206 @@ pub fn parse(sql: &str) -> Result<Query, ParseError> {
     }
 }
 
+// `limit $1`, where each `$n` is replaced with the nth value
+pub fn parse_with_parameters(
+    sql: &str,
+    parameters: &[serde_json::Value],
+) -> Result<Query, ParseError> {
+    let mut query = parse(sql)?;
+
+    bind_query_parameters(&mut query, parameters)?;
+
+    Ok(query)
+}
+
+fn bind_query_parameters(
+    query: &mut Query,
+    parameters: &[serde_json::Value],
+) -> Result<(), ParseError> {
+    let bind_all = |exprs: &mut [Expr]| {
+        exprs
+            .iter_mut()
+            .try_for_each(|expr| bind_expr_parameters(expr, parameters))
+    };
+
+    match query {
+        Query::From(_) | Query::Describe(_) | Query::ShowTables => Ok(()),
+        Query::TableFunction(TableFunctionCall { args, .. }) => bind_all(args),
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => {
+            bind_query_parameters(left_from, parameters)?;
+            bind_query_parameters(right_from, parameters)
+        }
+        Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            on,
+            ..
+        }) => {
+            bind_expr_parameters(on, parameters)?;
+            bind_query_parameters(left_from, parameters)?;
+            bind_query_parameters(right_from, parameters)
+        }
+        Query::Filter(Filter { from, filter }) => {
+            bind_expr_parameters(filter, parameters)?;
+            bind_query_parameters(from, parameters)
+        }
+        Query::Project(Project { from, fields }) => {
+            bind_all(fields)?;
+            bind_query_parameters(from, parameters)
+        }
+        Query::GroupBy(GroupBy {
+            from,
+            group_by,
+            having,
+            fields,
+        }) => {
+            bind_all(group_by)?;
+            bind_all(fields)?;
+            bind_all(having.as_mut_slice())?;
+            bind_query_parameters(from, parameters)
+        }
+        Query::Limit(Limit {
+            from,
+            limit,
+            offset,
+        }) => {
+            bind_all(limit.as_mut_slice())?;
+            bind_all(offset.as_mut_slice())?;
+            bind_query_parameters(from, parameters)
+        }
+        Query::Explain(Explain { query: from })
+        | Query::Sample(Sample { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::OrderBy(OrderBy { from, .. }) => bind_query_parameters(from, parameters),
+    }
+}
+
+fn bind_expr_parameters(
+    expr: &mut Expr,
+    parameters: &[serde_json::Value],
+) -> Result<(), ParseError> {
+    let bind = |expr: &mut Expr| bind_expr_parameters(expr, parameters);
+
+    match expr {
+        Expr::Parameter { index } => {
+            let literal = index
+                .checked_sub(1)
+                .and_then(|index| parameters.get(index))
+                .ok_or(ParseError::ParameterNotGiven { index: *index })?
+                .clone();
+            *expr = Expr::Literal { literal };
+            Ok(())
+        }
+        Expr::Column { .. } | Expr::Literal { .. } | Expr::Wildcard { .. } => Ok(()),
+        Expr::BinaryOperation { left, right, .. }
+        | Expr::Quantified { left, right, .. }
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        }
+        | Expr::Index {
+            expr: left,
+            index: right,
+        } => {
+            bind(left)?;
+            bind(right)
+        }
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. } => bind(expr),
+        Expr::FunctionCall { args: items, .. } | Expr::Array { items } => {
+            items.iter_mut().try_for_each(bind)
+        }
+        Expr::InList { expr, list, .. } => {
+            bind(expr)?;
+            list.iter_mut().try_for_each(bind)
+        }
+        Expr::InSubquery { expr, subquery, .. }
+        | Expr::QuantifiedSubquery {
+            left: expr,
+            subquery,
+            ..
+        } => {
+            bind(expr)?;
+            bind_query_parameters(subquery, parameters)
+        }
+        Expr::Exists { subquery, .. } => bind_query_parameters(subquery, parameters),
+    }
+}
+
 fn from_statement(statement: &ast::Statement) -> Result<Query, ParseError> {
     match statement {
         ast::Statement::Query(query) => from_query(query),
+        // `describe Album` and `show columns from Album` are the same thing
+        ast::Statement::ExplainTable {
+            describe_alias: ast::DescribeAlias::Describe | ast::DescribeAlias::Desc,
+            hive_format: None,
+            has_table_keyword: _,
+            table_name,
+        }
+        | ast::Statement::ShowColumns {
+            extended: false,
+            full: false,
+            show_options:
+                ast::ShowStatementOptions {
+                    show_in:
+                        Some(ast::ShowStatementIn {
+                            parent_name: Some(table_name),
+                            ..
+                        }),
+                    starts_with: None,
+                    limit: None,
+                    limit_from: None,
+                    filter_position: None,
+                },
+        } => Ok(Query::Describe(Describe {
+            table_name: table_name_from_object_name(table_name)?,
+        })),
+        ast::Statement::Explain {
+            describe_alias: ast::DescribeAlias::Explain,
+            analyze: false,
+            verbose: false,
+            query_plan: false,
+            estimate: false,
+            statement,
+            format: None,
+            options: None,
+        } => match statement.as_ref() {
+            ast::Statement::Query(query) => Ok(Query::Explain(Explain {
+                query: Box::new(from_query(query)?),
+            })),
+            _ => Err(ParseError::OnlyQueryIsSupported),
+        },
+        ast::Statement::ShowTables {
+            terse: false,
+            history: false,
+            extended: false,
+            full: false,
+            external: false,
+            show_options:
+                ast::ShowStatementOptions {
+                    show_in: None,
+                    starts_with: None,
+                    limit: None,
+                    limit_from: None,
+                    filter_position: None,
+                },
+        } => Ok(Query::ShowTables),
         _ => Err(ParseError::OnlyQueryIsSupported),
     }
 }
@@ -139,19 +398,16 @@ fn from_query(query: &ast::Query) -> Result<Query, ParseError> {
         return Err(ParseError::PipeOperatorsNotSupported);
     }
 
-    let mut query = from_body(body)?;
+    let order_by_exprs = order_by.as_ref().map(from_order_by).transpose()?;
 
-    if let Some(order_by) = order_by {
-        query = Query::OrderBy(OrderBy {
-            from: Box::new(query),
-            order_by_exprs: from_order_by(order_by)?,
-        });
-    }
+    let mut query = from_body(body, order_by_exprs)?;
 
-    if let Some(limit) = limit_clause {
+    if let Some(limit_clause) = limit_clause {
+        let (limit, offset) = from_limit(limit_clause)?;
         query = Query::Limit(Limit {
             from: Box::new(query),
-            limit: from_limit(limit)?,
+            limit,
+            offset,
         });
     }
 
@@ -182,7 +438,7 @@ fn from_order_by(order_by: &ast::OrderBy) -> Result<Vec<OrderByExpr>, ParseError
         .collect()
 }
 
-fn from_limit(limit: &ast::LimitClause) -> Result<u64, ParseError> {
+fn from_limit(limit: &ast::LimitClause) -> Result<(Option<Expr>, Option<Expr>), ParseError> {
     match limit {
         LimitClause::OffsetCommaLimit { .. } => Err(ParseError::OffsetCommaLimitNotSupported),
         LimitClause::LimitOffset {
@@ -190,36 +446,66 @@ fn from_limit(limit: &ast::LimitClause) -> Result<u64, ParseError> {
             offset,
             limit_by,
         } => {
-            if offset.is_some() {
-                return Err(ParseError::OffsetNotSupported);
-            }
-
             if !limit_by.is_empty() {
                 return Err(ParseError::LimitByNotSupported);
             }
 
-            if let Some(limit) = limit {
-                let value = value_from_selection(limit)?;
+            let limit = limit.as_ref().map(from_selection).transpose()?;
+            let offset = offset
+                .as_ref()
+                .map(|offset| from_selection(&offset.value))
+                .transpose()?;
 
-                match value {
-                    serde_json::Value::Number(a) => a.as_u64().ok_or(ParseError::LimitMustBeInt),
-                    _ => Err(ParseError::LimitMustBeInt),
-                }
-            } else {
-                Err(ParseError::LimitMustBeInt)
-            }
+            Ok((limit, offset))
         }
     }
 }
 
-fn from_body(body: &ast::SetExpr) -> Result<Query, ParseError> {
-    match body {
-        ast::SetExpr::Select(select) => from_select(select),
-        _ => Err(ParseError::OnlySelectIsSupported),
-    }
+fn from_body(
+    body: &ast::SetExpr,
+    order_by_exprs: Option<Vec<OrderByExpr>>,
+) -> Result<Query, ParseError> {
+    let query = match body {
+        ast::SetExpr::Select(select) => return from_select(select, order_by_exprs),
+        ast::SetExpr::Query(query) => from_query(query)?,
+        ast::SetExpr::SetOperation {
+            op: ast::SetOperator::Union,
+            set_quantifier,
+            left,
+            right,
+        } => {
+            let union = Query::Union(Union {
+                left: Box::new(from_body(left, None)?),
+                right: Box::new(from_body(right, None)?),
+            });
+
+            match set_quantifier {
+                ast::SetQuantifier::All => union,
+                ast::SetQuantifier::Distinct | ast::SetQuantifier::None => {
+                    Query::Distinct(Distinct {
+                        from: Box::new(union),
+                    })
+                }
+                _ => return Err(ParseError::SetQuantifierNotSupported),
+            }
+        }
+        _ => return Err(ParseError::OnlySelectIsSupported),
+    };
+
+    // sorting applies to the combined rows, so can only use output columns
+    Ok(match order_by_exprs {
+        Some(order_by_exprs) => Query::OrderBy(OrderBy {
+            from: Box::new(query),
+            order_by_exprs,
+        }),
+        None => query,
+    })
 }
 
-fn from_select(select: &ast::Select) -> Result<Query, ParseError> {
+fn from_select(
+    select: &ast::Select,
+    order_by_exprs: Option<Vec<OrderByExpr>>,
+) -> Result<Query, ParseError> {
     let ast::Select {
         select_token: _,
         distinct,
@@ -235,7 +521,7 @@ fn from_select(select: &ast::Select) -> Result<Query, ParseError> {
         cluster_by: _,
         distribute_by: _,
         sort_by,
-        having: _,
+        having,
         named_window: _,
         qualify: _,
         window_before_qualify: _,
@@ -248,22 +534,24 @@ fn from_select(select: &ast::Select) -> Result<Query, ParseError> {
         return Err(ParseError::IntoNotSupported);
     }
 
-    if distinct.is_some() {
-        return Err(ParseError::DistinctNotSupported);
-    }
+    let distinct = match distinct {
+        None => false,
+        Some(ast::Distinct::Distinct) => true,
+        Some(ast::Distinct::On(_)) => return Err(ParseError::DistinctNotSupported),
+    };
 
-    match group_by {
-        ast::GroupByExpr::All(a) => {
-            if !a.is_empty() {
+    let grouping_sets = match group_by {
+        ast::GroupByExpr::Expressions(exprs, modifiers) if exprs.is_empty() => {
+            if !modifiers.is_empty() {
                 return Err(ParseError::GroupByNotSupported);
             }
+            vec![]
         }
-        ast::GroupByExpr::Expressions(a, b) => {
-            if !a.is_empty() || !b.is_empty() {
-                return Err(ParseError::GroupByNotSupported);
-            }
+        ast::GroupByExpr::Expressions(exprs, modifiers) if modifiers.is_empty() => {
+            from_group_by(exprs)?
         }
-    }
+        _ => return Err(ParseError::GroupByNotSupported),
+    };
 
     if !sort_by.is_empty() {
         return Err(ParseError::SortByNotSupported);
@@ -278,20 +566,241 @@ fn from_select(select: &ast::Select) -> Result<Query, ParseError> {
         });
     }
 
-    if let Some(fields) = from_projection(projection)? {
+    let fields = from_projection(projection)?;
+
+    let from_columns = query::from_columns(&query);
+    let grouping_sets = group_by_references(grouping_sets, fields.as_deref(), &from_columns)?;
+
+    let mut having = having.as_ref().map(from_selection).transpose()?;
+    if let Some(having) = &mut having {
+        having_aliases(having, fields.as_deref().unwrap_or_default(), &from_columns);
+    }
+
+    if having.is_some() && grouping_sets.is_empty() {
+        return Err(ParseError::HavingRequiresGroupBy);
+    }
+
+    if !grouping_sets.is_empty() {
+        // grouping replaces the projection, so sorting has to come after
+        // it and can only use the columns that are selected
+        query = group_by_grouping_sets(
+            &query,
+            &grouping_sets,
+            having.as_ref(),
+            &fields.ok_or(ParseError::GroupByRequiresFields)?,
+        );
+
+        if distinct {
+            query = Query::Distinct(Distinct {
+                from: Box::new(query),
+            });
+        }
+
+        if let Some(order_by_exprs) = order_by_exprs {
+            query = Query::OrderBy(OrderBy {
+                from: Box::new(query),
+                order_by_exprs,
+            });
+        }
+
+        return Ok(query);
+    }
+
+    let (order_by_exprs, order_after_projection) = match order_by_exprs {
+        Some(order_by_exprs) => {
+            let (order_by_exprs, after) = order_by_aliases(order_by_exprs, fields.as_deref());
+            (Some(order_by_exprs), after)
+        }
+        None => (None, false),
+    };
+
+    // sort before projecting so we can order by columns that are not
+    // selected, unless we're sorting by something that only exists after
+    let order_by = |query| match &order_by_exprs {
+        Some(order_by_exprs) => Query::OrderBy(OrderBy {
+            from: Box::new(query),
+            order_by_exprs: order_by_exprs.clone(),
+        }),
+        None => query,
+    };
+
+    if !order_after_projection {
+        query = order_by(query);
+    }
+
+    if let Some(fields) = fields {
         query = Query::Project(Project {
             from: Box::new(query),
             fields,
         });
     }
 
+    if order_after_projection {
+        query = order_by(query);
+    }
+
+    // after sorting, so the first of each set of duplicates is kept
+    if distinct {
+        query = Query::Distinct(Distinct {
+            from: Box::new(query),
+        });
+    }
+
     Ok(query)
 }
 
+// ordering by an alias of a plain column is the same as ordering by the
+// column, which can be done before projecting. an alias of anything else only
+// exists after projecting, so the sort has to happen then instead
+fn order_by_aliases(
+    order_by_exprs: Vec<OrderByExpr>,
+    fields: Option<&[Expr]>,
+) -> (Vec<OrderByExpr>, bool) {
+    let mut after_projection = false;
+
+    let order_by_exprs = order_by_exprs
+        .into_iter()
+        .map(
+            |order_by_expr| match find_alias(fields.unwrap_or_default(), &order_by_expr.column) {
+                Some(Expr::Column { column }) => OrderByExpr {
+                    column: column.clone(),
+                    ..order_by_expr
+                },
+                Some(_) => {
+                    after_projection = true;
+                    order_by_expr
+                }
+                None => order_by_expr,
+            },
+        )
+        .collect();
+
+    (order_by_exprs, after_projection)
+}
+
+// the expression selected as `column`, if it is an unqualified alias
+fn find_alias<'a>(fields: &'a [Expr], column: &Column) -> Option<&'a Expr> {
+    if column.table_alias.is_some() {
+        return None;
+    }
+
+    fields.iter().find_map(|field| match field {
+        Expr::Alias { expr, alias } if *alias == *column.name => Some(expr.as_ref()),
+        _ => None,
+    })
+}
+
+// the selected expression an unqualified name is an alias of, unless a
+// column of the tables being grouped has that name, which wins like it does
+// in postgres
+fn input_or_alias<'a>(
+    column: &Column,
+    fields: &'a [Expr],
+    from_columns: &[Column],
+) -> Option<&'a Expr> {
+    if from_columns
+        .iter()
+        .any(|from_column| from_column.name == column.name)
+    {
+        return None;
+    }
+
+    find_alias(fields, column)
+}
+
+// `having c > 20` can use the alias of `count(*) as c`, which means the same
+// as writing `count(*)` out again. aggregates and subqueries are left alone
+fn having_aliases(expr: &mut Expr, fields: &[Expr], from_columns: &[Column]) {
+    let resolve = |expr: &mut Expr| having_aliases(expr, fields, from_columns);
+
+    match expr {
+        Expr::Column { column } => {
+            if let Some(aliased) = input_or_alias(column, fields, from_columns) {
+                *expr = aliased.clone();
+            }
+        }
+        Expr::FunctionCall {
+            function_name: FunctionName::Aggregate(_),
+            ..
+        }
+        | Expr::Literal { .. }
+        | Expr::Wildcard { .. }
+        | Expr::Parameter { .. }
+        | Expr::Exists { .. } => {}
+        Expr::BinaryOperation { left, right, .. }
+        | Expr::Quantified { left, right, .. }
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        }
+        | Expr::Index {
+            expr: left,
+            index: right,
+        } => {
+            resolve(left);
+            resolve(right);
+        }
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. }
+        | Expr::QuantifiedSubquery { left: expr, .. } => resolve(expr),
+        Expr::FunctionCall { args: items, .. } | Expr::Array { items } => {
+            items.iter_mut().for_each(resolve);
+        }
+        Expr::InList { expr, list, .. } => {
+            resolve(expr);
+            list.iter_mut().for_each(resolve);
+        }
+    }
+}
+
+// `group by 1` groups by the first thing selected, and `group by name` by
+// whatever is selected as `name`, looked up the same way as ordering by an
+// alias, but a column of the tables being grouped wins
+fn group_by_references(
+    grouping_sets: Vec<Vec<Expr>>,
+    fields: Option<&[Expr]>,
+    from_columns: &[Column],
+) -> Result<Vec<Vec<Expr>>, ParseError> {
+    let fields = fields.unwrap_or_default();
+
+    let resolve = |expr: Expr| match expr {
+        Expr::Literal {
+            literal: literal @ serde_json::Value::Number(_),
+        } => {
+            let field = literal
+                .as_u64()
+                .and_then(|position| usize::try_from(position).ok()?.checked_sub(1))
+                .and_then(|index| fields.get(index));
+
+            match field {
+                Some(Expr::Alias { expr, .. }) => Ok(*expr.clone()),
+                Some(Expr::Wildcard { .. }) | None => {
+                    Err(ParseError::GroupByPositionNotInSelectList { position: literal })
+                }
+                Some(field) => Ok(field.clone()),
+            }
+        }
+        Expr::Column { column } => Ok(input_or_alias(&column, fields, from_columns)
+            .cloned()
+            .unwrap_or(Expr::Column { column })),
+        expr => Ok(expr),
+    };
+
+    grouping_sets
+        .into_iter()
+        .map(|grouping_set| grouping_set.into_iter().map(resolve).collect())
+        .collect()
+}
+
 fn identifier_from_selection(expr: &ast::Expr) -> Result<Column, ParseError> {
     match expr {
         ast::Expr::Identifier(ident) => Ok(Column {
-            name: ident.value.to_string(),
+            name: ident.value.as_str().into(),
             table_alias: None,
         }),
         ast::Expr::CompoundIdentifier(idents) => {
@@ -299,8 +808,8 @@ fn identifier_from_selection(expr: &ast::Expr) -> Result<Column, ParseError> {
                 (idents.first(), idents.get(1), idents.get(2))
             {
                 Ok(Column {
-                    name: column.to_string(),
-                    table_alias: Some(TableAlias(table_alias.to_string())),
+                    name: column.value.as_str().into(),
+                    table_alias: Some(TableAlias(table_alias.value.as_str().into())),
                 })
             } else {
                 Err(ParseError::ExpectedTwoIdents)
@@ -312,15 +821,195 @@ fn identifier_from_selection(expr: &ast::Expr) -> Result<Column, ParseError> {
     }
 }
 
+// postgres draws the line at the same place, after that there are too many
+// grouping sets to be useful
+const MAX_CUBE_ITEMS: usize = 12;
+
+// `group by a, rollup (b, c)` is the grouping sets `(a, b, c)`, `(a, b)` and
+// `(a)`. each item multiplies the sets so far by its own sets, and a plain
+// expression only has one
+fn from_group_by(exprs: &[ast::Expr]) -> Result<Vec<Vec<Expr>>, ParseError> {
+    let mut grouping_sets = vec![vec![]];
+
+    for expr in exprs {
+        let item_sets = match expr {
+            // `rollup (a, b)` is `(a, b)`, `(a)` and `()`
+            ast::Expr::Rollup(items) => {
+                let items = from_grouping_items(items)?;
+                (0..=items.len())
+                    .rev()
+                    .map(|len| items[..len].concat())
+                    .collect()
+            }
+            // `cube (a, b)` is `(a, b)`, `(a)`, `(b)` and `()`
+            ast::Expr::Cube(items) => {
+                let items = from_grouping_items(items)?;
+                if items.len() > MAX_CUBE_ITEMS {
+                    return Err(ParseError::CubeTooLarge { items: items.len() });
+                }
+                (0..1_usize << items.len())
+                    .rev()
+                    .map(|mask| {
+                        items
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _)| mask & (1 << (items.len() - 1 - index)) != 0)
+                            .flat_map(|(_, item)| item.clone())
+                            .collect()
+                    })
+                    .collect()
+            }
+            ast::Expr::GroupingSets(sets) => from_grouping_items(sets)?,
+            expr => vec![vec![from_selection(expr)?]],
+        };
+
+        grouping_sets = grouping_sets
+            .iter()
+            .flat_map(|set| {
+                item_sets
+                    .iter()
+                    .map(move |item_set| [set.as_slice(), item_set].concat())
+            })
+            .collect();
+    }
+
+    Ok(grouping_sets)
+}
+
+fn from_grouping_items(items: &[Vec<ast::Expr>]) -> Result<Vec<Vec<Expr>>, ParseError> {
+    items
+        .iter()
+        .map(|item| item.iter().map(from_selection).collect())
+        .collect()
+}
+
+// one group by per grouping set, with their rows put together in order. in
+// each one, anything grouped on by another set but not this one is null,
+// apart from inside aggregates, which still see the real values
+fn group_by_grouping_sets(
+    from: &Query,
+    grouping_sets: &[Vec<Expr>],
+    having: Option<&Expr>,
+    fields: &[Expr],
+) -> Query {
+    let grouped: Vec<&Expr> = grouping_sets.iter().flatten().collect();
+
+    grouping_sets
+        .iter()
+        .map(|grouping_set| {
+            let omitted: Vec<&Expr> = grouped
+                .iter()
+                .copied()
+                .filter(|expr| !grouping_set.contains(expr))
+                .collect();
+
+            let fields = fields
+                .iter()
+                .map(|field| match field {
+                    // keep the column name, in case this set comes first
+                    Expr::Column { column } if omitted.contains(&field) => Expr::Alias {
+                        expr: Box::new(Expr::Literal {
+                            literal: serde_json::Value::Null,
+                        }),
+                        alias: column.name.to_string(),
+                    },
+                    field => {
+                        let mut field = field.clone();
+                        null_omitted(&mut field, &omitted);
+                        field
+                    }
+                })
+                .collect();
+
+            Query::GroupBy(GroupBy {
+                from: Box::new(from.clone()),
+                group_by: grouping_set.clone(),
+                having: having.map(|having| {
+                    let mut having = having.clone();
+                    null_omitted(&mut having, &omitted);
+                    having
+                }),
+                fields,
+            })
+        })
+        .reduce(|left, right| {
+            Query::Union(Union {
+                left: Box::new(left),
+                right: Box::new(right),
+            })
+        })
+        .unwrap_or_else(|| from.clone())
+}
+
+fn null_omitted(expr: &mut Expr, omitted: &[&Expr]) {
+    if omitted.contains(&&*expr) {
+        *expr = Expr::Literal {
+            literal: serde_json::Value::Null,
+        };
+        return;
+    }
+
+    let null = |expr: &mut Expr| null_omitted(expr, omitted);
+
+    match expr {
+        Expr::FunctionCall {
+            function_name: FunctionName::Aggregate(_),
+            ..
+        }
+        | Expr::Column { .. }
+        | Expr::Literal { .. }
+        | Expr::Wildcard { .. }
+        | Expr::Parameter { .. }
+        | Expr::Exists { .. } => {}
+        Expr::BinaryOperation { left, right, .. }
+        | Expr::Quantified { left, right, .. }
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        }
+        | Expr::Index {
+            expr: left,
+            index: right,
+        } => {
+            null(left);
+            null(right);
+        }
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. }
+        | Expr::QuantifiedSubquery { left: expr, .. } => null(expr),
+        Expr::FunctionCall { args: items, .. } | Expr::Array { items } => {
+            items.iter_mut().for_each(null);
+        }
+        Expr::InList { expr, list, .. } => {
+            null(expr);
+            list.iter_mut().for_each(null);
+        }
+    }
+}
+
 fn from_binary_operator(op: &ast::BinaryOperator) -> Result<Op, ParseError> {
     match op {
         ast::BinaryOperator::Eq => Ok(Op::Equals),
+        ast::BinaryOperator::NotEq => Ok(Op::NotEquals),
         ast::BinaryOperator::Gt => Ok(Op::GreaterThan),
         ast::BinaryOperator::GtEq => Ok(Op::GreaterThanOrEqual),
         ast::BinaryOperator::Lt => Ok(Op::LessThan),
         ast::BinaryOperator::LtEq => Ok(Op::LessThanOrEqual),
         ast::BinaryOperator::Plus => Ok(Op::Add),
         ast::BinaryOperator::Minus => Ok(Op::Subtract),
+        ast::BinaryOperator::Multiply => Ok(Op::Multiply),
+        ast::BinaryOperator::Arrow => Ok(Op::JsonGet),
+        ast::BinaryOperator::LongArrow => Ok(Op::JsonGetText),
+        ast::BinaryOperator::AtArrow => Ok(Op::Contains),
+        ast::BinaryOperator::ArrowAt => Ok(Op::ContainedBy),
+        ast::BinaryOperator::And => Ok(Op::And),
+        ast::BinaryOperator::Or => Ok(Op::Or),
+        ast::BinaryOperator::StringConcat => Ok(Op::Concat),
         _ => Err(ParseError::UnknownOperator),
     }
 }
@@ -331,19 +1020,33 @@ fn value_from_selection(expr: &ast::Expr) -> Result<serde_json::Value, ParseErro
             let ast::ValueWithSpan {
                 value: inner_value, ..
             } = value;
-            if let ast::Value::SingleQuotedString(s) = inner_value {
-                Ok(s.clone().into())
-            } else {
-                // last resort, stringify the thing and throw it at serde_json decode
-                let val_string = value.to_string();
-                serde_json::from_str(val_string.as_str())
-                    .map_err(|e| ParseError::SerdeJsonError(val_string, e))
+            match inner_value {
+                ast::Value::SingleQuotedString(s) => Ok(s.clone().into()),
+                ast::Value::Null => Ok(serde_json::Value::Null),
+                _ => {
+                    // last resort, stringify the thing and throw it at serde_json decode
+                    let val_string = value.to_string();
+                    serde_json::from_str(val_string.as_str())
+                        .map_err(|e| ParseError::SerdeJsonError(val_string, e))
+                }
             }
         }
-        _ => Err(ParseError::ExpectedValue(expr.clone())),
+        _ => Err(ParseError::ExpectedValue(Box::new(expr.clone()))),
     }
 }
 
+// only numbered `$1` parameters, since `?` ones would depend on the order we
+// look at the query in
+fn from_parameter(name: &str) -> Result<Expr, ParseError> {
+    name.strip_prefix('$')
+        .and_then(|index| index.parse().ok())
+        .filter(|index| *index > 0)
+        .map(|index| Expr::Parameter { index })
+        .ok_or_else(|| ParseError::UnsupportedParameter {
+            name: name.to_string(),
+        })
+}
+
 fn from_selection(expr: &ast::Expr) -> Result<Expr, ParseError> {
     match expr {
         ast::Expr::BinaryOp { left, op, right } => Ok(Expr::BinaryOperation {
@@ -351,23 +1054,16 @@ fn from_selection(expr: &ast::Expr) -> Result<Expr, ParseError> {
             op: from_binary_operator(op)?,
             right: Box::new(from_selection(right)?),
         }),
-        ast::Expr::Value(value) => {
-            let ast::ValueWithSpan {
-                value: inner_value, ..
-            } = value;
-            let literal = if let ast::Value::SingleQuotedString(s) = inner_value {
-                Ok(s.clone().into())
-            } else {
-                // last resort, stringify the thing and throw it at serde_json decode
-                let val_string = value.to_string();
-                serde_json::from_str(val_string.as_str())
-                    .map_err(|e| ParseError::SerdeJsonError(val_string, e))
-            }?;
-            Ok(Expr::Literal { literal })
-        }
+        ast::Expr::Value(ast::ValueWithSpan {
+            value: ast::Value::Placeholder(name),
+            ..
+        }) => from_parameter(name),
+        ast::Expr::Value(_) => Ok(Expr::Literal {
+            literal: value_from_selection(expr)?,
+        }),
         ast::Expr::Identifier(ident) => Ok(Expr::Column {
             column: Column {
-                name: ident.value.to_string(),
+                name: ident.value.as_str().into(),
                 table_alias: None,
             },
         }),
@@ -377,8 +1073,8 @@ fn from_selection(expr: &ast::Expr) -> Result<Expr, ParseError> {
             {
                 Ok(Expr::Column {
                     column: Column {
-                        name: column.to_string(),
-                        table_alias: Some(TableAlias(table_alias.to_string())),
+                        name: column.value.as_str().into(),
+                        table_alias: Some(TableAlias(table_alias.value.as_str().into())),
                     },
                 })
             } else {
@@ -388,13 +1084,323 @@ fn from_selection(expr: &ast::Expr) -> Result<Expr, ParseError> {
         ast::Expr::Nested(expr) => Ok(Expr::Nested {
             expr: Box::new(from_selection(expr)?),
         }),
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Not,
+            expr,
+        } => Ok(Expr::Not {
+            expr: Box::new(from_selection(expr)?),
+        }),
+        // `-1` is a literal, anything else is taken away from zero
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr: inner,
+        } => match from_selection(inner)? {
+            Expr::Literal {
+                literal: serde_json::Value::Number(number),
+            } => {
+                let literal = number
+                    .as_i64()
+                    .and_then(i64::checked_neg)
+                    .map(Into::into)
+                    .or_else(|| number.as_f64().map(|f| (-f).into()))
+                    .ok_or_else(|| ParseError::UnknownExprPart {
+                        expr: expr.to_string(),
+                    })?;
+                Ok(Expr::Literal { literal })
+            }
+            inner => Ok(Expr::BinaryOperation {
+                left: Box::new(Expr::Literal { literal: 0.into() }),
+                op: Op::Subtract,
+                right: Box::new(inner),
+            }),
+        },
         ast::Expr::Function(function) => from_function(function),
+        // `floor(x)` and `ceil(x)` are parsed specially to allow `ceil(x to day)`
+        ast::Expr::Floor {
+            expr: inner,
+            field: ast::CeilFloorKind::DateTimeField(ast::DateTimeField::NoDateTime),
+        } => Ok(Expr::FunctionCall {
+            function_name: FunctionName::Scalar(ScalarFunctionName::Floor),
+            args: vec![from_selection(inner)?],
+            distinct: false,
+        }),
+        ast::Expr::Ceil {
+            expr: inner,
+            field: ast::CeilFloorKind::DateTimeField(ast::DateTimeField::NoDateTime),
+        } => Ok(Expr::FunctionCall {
+            function_name: FunctionName::Scalar(ScalarFunctionName::Ceil),
+            args: vec![from_selection(inner)?],
+            distinct: false,
+        }),
+        // `substr(x, 2, 3)` and `substring(x from 2 for 3)` are both parsed
+        // specially, a missing start is the first character
+        ast::Expr::Substring {
+            expr,
+            substring_from,
+            substring_for,
+            ..
+        } => {
+            let start = match substring_from {
+                Some(substring_from) => from_selection(substring_from)?,
+                None => Expr::Literal { literal: 1.into() },
+            };
+
+            let mut args = vec![from_selection(expr)?, start];
+            if let Some(substring_for) = substring_for {
+                args.push(from_selection(substring_for)?);
+            }
+
+            Ok(Expr::FunctionCall {
+                function_name: FunctionName::Scalar(ScalarFunctionName::Substr),
+                args,
+                distinct: false,
+            })
+        }
+        ast::Expr::InList {
+            expr,
+            list,
+            negated,
+        } => Ok(Expr::InList {
+            expr: Box::new(from_selection(expr)?),
+            list: list.iter().map(from_selection).collect::<Result<_, _>>()?,
+            negated: *negated,
+        }),
+        ast::Expr::Cast {
+            kind: ast::CastKind::Cast | ast::CastKind::DoubleColon,
+            expr,
+            data_type,
+            format: None,
+        } => Ok(Expr::Cast {
+            expr: Box::new(from_selection(expr)?),
+            data_type: from_data_type(data_type)?,
+        }),
+        // `date '2021-01-01'` is a cast of the string
+        ast::Expr::TypedString { data_type, value } => Ok(Expr::Cast {
+            expr: Box::new(Expr::Literal {
+                literal: value
+                    .clone()
+                    .into_string()
+                    .ok_or_else(|| ParseError::UnknownExprPart {
+                        expr: expr.to_string(),
+                    })?
+                    .into(),
+            }),
+            data_type: from_data_type(data_type)?,
+        }),
+        // `interval '7 days'` and `interval '7' day` are casts of the text
+        ast::Expr::Interval(ast::Interval {
+            value,
+            leading_field,
+            leading_precision: None,
+            last_field: None,
+            fractional_seconds_precision: None,
+        }) => match from_selection(value)? {
+            Expr::Literal {
+                literal: serde_json::Value::String(value),
+            } => Ok(Expr::Cast {
+                expr: Box::new(Expr::Literal {
+                    literal: match leading_field {
+                        Some(field) => format!("{value} {field}").into(),
+                        None => value.into(),
+                    },
+                }),
+                data_type: DataType::Interval,
+            }),
+            _ => Err(ParseError::UnknownExprPart {
+                expr: expr.to_string(),
+            }),
+        },
+        // `extract(year from x)` is `date_part('year', x)`
+        ast::Expr::Extract {
+            field, expr: inner, ..
+        } => Ok(Expr::FunctionCall {
+            function_name: FunctionName::Scalar(ScalarFunctionName::DatePart),
+            args: vec![
+                Expr::Literal {
+                    literal: field.to_string().to_lowercase().into(),
+                },
+                from_selection(inner)?,
+            ],
+            distinct: false,
+        }),
+        ast::Expr::IsNull(expr) => Ok(Expr::IsNull {
+            expr: Box::new(from_selection(expr)?),
+            negated: false,
+        }),
+        ast::Expr::IsNotNull(expr) => Ok(Expr::IsNull {
+            expr: Box::new(from_selection(expr)?),
+            negated: true,
+        }),
+        ast::Expr::Exists { subquery, negated } => Ok(Expr::Exists {
+            subquery: Box::new(from_query(subquery)?),
+            negated: *negated,
+        }),
+        ast::Expr::InSubquery {
+            expr,
+            subquery,
+            negated,
+        } => Ok(Expr::InSubquery {
+            expr: Box::new(from_selection(expr)?),
+            subquery: Box::new(from_body(subquery, None)?),
+            negated: *negated,
+        }),
+        ast::Expr::Like {
+            negated,
+            any,
+            expr,
+            pattern,
+            escape_char,
+        } => from_like(*negated, *any, expr, pattern, escape_char.as_deref(), false),
+        ast::Expr::ILike {
+            negated,
+            any,
+            expr,
+            pattern,
+            escape_char,
+        } => from_like(*negated, *any, expr, pattern, escape_char.as_deref(), true),
+        ast::Expr::AnyOp {
+            left,
+            compare_op,
+            right,
+            is_some: _,
+        } => from_quantified(left, compare_op, Quantifier::Any, right),
+        ast::Expr::AllOp {
+            left,
+            compare_op,
+            right,
+        } => from_quantified(left, compare_op, Quantifier::All, right),
+        ast::Expr::Array(ast::Array { elem, .. }) => Ok(Expr::Array {
+            items: elem.iter().map(from_selection).collect::<Result<_, _>>()?,
+        }),
+        // a row value like `(ArtistId, AlbumId)` is evaluated as an array, which
+        // already compares item by item
+        ast::Expr::Tuple(items) => Ok(Expr::Array {
+            items: items.iter().map(from_selection).collect::<Result<_, _>>()?,
+        }),
+        ast::Expr::CompoundFieldAccess { root, access_chain } => {
+            access_chain
+                .iter()
+                .try_fold(from_selection(root)?, |inner, access| match access {
+                    ast::AccessExpr::Subscript(ast::Subscript::Index { index }) => {
+                        Ok(Expr::Index {
+                            expr: Box::new(inner),
+                            index: Box::new(from_selection(index)?),
+                        })
+                    }
+                    ast::AccessExpr::Subscript(ast::Subscript::Slice { .. }) => {
+                        Err(ParseError::ArraySliceNotSupported)
+                    }
+                    ast::AccessExpr::Dot(_) => Err(ParseError::UnknownExprPart {
+                        expr: expr.to_string(),
+                    }),
+                })
+        }
         _ => Err(ParseError::UnknownExprPart {
             expr: expr.to_string(),
         }),
     }
 }
 
+// `x > all (array)`, or `x > all (select ...)`, which is run like `in` with
+// a subquery
+fn from_quantified(
+    left: &ast::Expr,
+    compare_op: &ast::BinaryOperator,
+    quantifier: Quantifier,
+    right: &ast::Expr,
+) -> Result<Expr, ParseError> {
+    let left = Box::new(from_selection(left)?);
+    let op = from_binary_operator(compare_op)?;
+
+    Ok(match right {
+        ast::Expr::Subquery(subquery) => Expr::QuantifiedSubquery {
+            left,
+            op,
+            quantifier,
+            subquery: Box::new(from_query(subquery)?),
+        },
+        right => Expr::Quantified {
+            left,
+            op,
+            quantifier,
+            right: Box::new(from_selection(right)?),
+        },
+    })
+}
+
+fn from_like(
+    negated: bool,
+    any: bool,
+    expr: &ast::Expr,
+    pattern: &ast::Expr,
+    escape_char: Option<&str>,
+    case_insensitive: bool,
+) -> Result<Expr, ParseError> {
+    if any {
+        return Err(ParseError::LikeAnyNotSupported);
+    }
+
+    // like postgres, backslash escapes unless something else is given, and
+    // `escape ''` turns escaping off
+    let escape = match escape_char {
+        None => Some('\\'),
+        Some(escape_char) => {
+            let mut chars = escape_char.chars();
+            match (chars.next(), chars.next()) {
+                (None, _) => None,
+                (Some(c), None) => Some(c),
+                _ => {
+                    return Err(ParseError::InvalidEscapeCharacter {
+                        found: escape_char.to_string(),
+                    })
+                }
+            }
+        }
+    };
+
+    Ok(Expr::Like {
+        expr: Box::new(from_selection(expr)?),
+        pattern: Box::new(from_selection(pattern)?),
+        negated,
+        case_insensitive,
+        escape,
+    })
+}
+
+fn from_data_type(data_type: &ast::DataType) -> Result<DataType, ParseError> {
+    match data_type {
+        ast::DataType::Int(_)
+        | ast::DataType::Int4(_)
+        | ast::DataType::Int8(_)
+        | ast::DataType::Integer(_)
+        | ast::DataType::SmallInt(_)
+        | ast::DataType::BigInt(_) => Ok(DataType::Integer),
+        ast::DataType::Float(_)
+        | ast::DataType::Float4
+        | ast::DataType::Float8
+        | ast::DataType::Real
+        | ast::DataType::Double(_)
+        | ast::DataType::DoublePrecision
+        | ast::DataType::Numeric(_)
+        | ast::DataType::Decimal(_) => Ok(DataType::Float),
+        ast::DataType::Text
+        | ast::DataType::String(_)
+        | ast::DataType::Varchar(_)
+        | ast::DataType::CharacterVarying(_) => Ok(DataType::Text),
+        ast::DataType::Bool | ast::DataType::Boolean => Ok(DataType::Boolean),
+        ast::DataType::Date => Ok(DataType::Date),
+        ast::DataType::Interval => Ok(DataType::Interval),
+        ast::DataType::Timestamp(
+            _,
+            ast::TimezoneInfo::None | ast::TimezoneInfo::WithoutTimeZone,
+        )
+        | ast::DataType::Datetime(_) => Ok(DataType::Timestamp),
+        _ => Err(ParseError::CastNotSupported {
+            data_type: data_type.to_string(),
+        }),
+    }
+}
+
 fn from_function(function: &ast::Function) -> Result<Expr, ParseError> {
     let ast::Function {
         name,
@@ -413,12 +1419,6 @@ fn from_function(function: &ast::Function) -> Result<Expr, ParseError> {
         ));
     }
 
-    if !within_group.is_empty() {
-        return Err(ParseError::Function(
-            FunctionParseError::WithinGroupNotSupported,
-        ));
-    }
-
     if filter.is_some() {
         return Err(ParseError::Function(FunctionParseError::FilterNotSupported));
     }
@@ -441,8 +1441,20 @@ fn from_function(function: &ast::Function) -> Result<Expr, ParseError> {
 
     let function_name = from_function_name(name).map_err(ParseError::Function)?;
 
-    let args = match args {
-        ast::FunctionArguments::None => Ok(vec![]),
+    // `count(*)` counts rows, which is the same as counting something that
+    // is never null
+    if function_name == FunctionName::Aggregate(AggregateFunctionName::Count)
+        && is_wildcard_argument(args)
+    {
+        return Ok(Expr::FunctionCall {
+            function_name,
+            args: vec![Expr::Literal { literal: 1.into() }],
+            distinct: false,
+        });
+    }
+
+    let (mut args, distinct) = match args {
+        ast::FunctionArguments::None => Ok((vec![], false)),
         ast::FunctionArguments::Subquery(_) => Err(ParseError::Function(
             FunctionParseError::SubQueryNotSupported,
         )),
@@ -451,15 +1463,68 @@ fn from_function(function: &ast::Function) -> Result<Expr, ParseError> {
         }
     }?;
 
+    // only aggregates see more than one value, so can skip repeated ones
+    if distinct && !matches!(function_name, FunctionName::Aggregate(_)) {
+        return Err(ParseError::Function(
+            FunctionParseError::DistinctNotSupported,
+        ));
+    }
+
+    // `percentile_cont(0.5) within group (order by x)`, the ordered expression
+    // goes first like the argument to any other aggregate
+    let is_ordered_set =
+        function_name == FunctionName::Aggregate(AggregateFunctionName::PercentileCont);
+
+    match within_group.as_slice() {
+        [] if is_ordered_set => {
+            return Err(ParseError::Function(
+                FunctionParseError::WithinGroupRequired,
+            ))
+        }
+        [] => {}
+        [ast::OrderByExpr {
+            expr,
+            options,
+            with_fill: None,
+        }] if is_ordered_set && options.asc != Some(false) && options.nulls_first.is_none() => {
+            args.insert(0, from_selection(expr)?);
+        }
+        _ => {
+            return Err(ParseError::Function(
+                FunctionParseError::WithinGroupNotSupported,
+            ))
+        }
+    }
+
     Ok(Expr::FunctionCall {
         function_name,
         args,
+        distinct,
     })
 }
 
+// just `(*)`
+fn is_wildcard_argument(args: &ast::FunctionArguments) -> bool {
+    let ast::FunctionArguments::List(FunctionArgumentList {
+        args,
+        clauses,
+        duplicate_treatment: None,
+    }) = args
+    else {
+        return false;
+    };
+
+    clauses.is_empty()
+        && matches!(
+            args.as_slice(),
+            [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)]
+        )
+}
+
+// the arguments, and whether they were marked `distinct`
 fn from_function_argument_list(
     function_argument_list: &FunctionArgumentList,
-) -> Result<Vec<Expr>, ParseError> {
+) -> Result<(Vec<Expr>, bool), ParseError> {
     let FunctionArgumentList {
         args,
         clauses,
@@ -472,25 +1537,30 @@ fn from_function_argument_list(
         ));
     }
 
-    if duplicate_treatment.is_some() {
-        return Err(ParseError::Function(
-            FunctionParseError::DuplicateTreatmentNotSupported,
-        ));
-    }
+    let distinct = matches!(duplicate_treatment, Some(ast::DuplicateTreatment::Distinct));
 
-    args.iter()
-        .map(|arg| match arg {
-            FunctionArg::Named { .. } => todo!("what"),
-            FunctionArg::ExprNamed { .. } => todo!("who"),
-            FunctionArg::Unnamed(expr) => from_function_arg_expr(expr),
-        })
-        .collect()
+    let args = args
+        .iter()
+        .map(from_function_arg)
+        .collect::<Result<_, _>>()?;
+
+    Ok((args, distinct))
+}
+
+fn from_function_arg(arg: &FunctionArg) -> Result<Expr, ParseError> {
+    match arg {
+        FunctionArg::Named { .. } => todo!("what"),
+        FunctionArg::ExprNamed { .. } => todo!("who"),
+        FunctionArg::Unnamed(expr) => from_function_arg_expr(expr),
+    }
 }
 
 fn from_function_arg_expr(arg: &FunctionArgExpr) -> Result<Expr, ParseError> {
     match arg {
         FunctionArgExpr::Expr(expr) => from_selection(expr),
-        _ => todo!("oh give up"),
+        FunctionArgExpr::Wildcard | FunctionArgExpr::QualifiedWildcard(_) => Err(
+            ParseError::Function(FunctionParseError::WildcardOnlyInCount),
+        ),
     }
 }
 
@@ -507,98 +1577,310 @@ fn from_function_name(object_name: &ast::ObjectName) -> Result<FunctionName, Fun
 
     match ident {
         "sum" => Ok(FunctionName::Aggregate(AggregateFunctionName::Sum)),
+        "count" => Ok(FunctionName::Aggregate(AggregateFunctionName::Count)),
+        "avg" => Ok(FunctionName::Aggregate(AggregateFunctionName::Avg)),
+        "min" => Ok(FunctionName::Aggregate(AggregateFunctionName::Min)),
+        "max" => Ok(FunctionName::Aggregate(AggregateFunctionName::Max)),
+        "array_agg" => Ok(FunctionName::Aggregate(AggregateFunctionName::ArrayAgg)),
+        "string_agg" => Ok(FunctionName::Aggregate(AggregateFunctionName::StringAgg)),
+        "variance" => Ok(FunctionName::Aggregate(AggregateFunctionName::Variance)),
+        "stddev" => Ok(FunctionName::Aggregate(AggregateFunctionName::Stddev)),
+        "percentile_cont" => Ok(FunctionName::Aggregate(
+            AggregateFunctionName::PercentileCont,
+        )),
+        "upper" => Ok(FunctionName::Scalar(ScalarFunctionName::Upper)),
+        "lower" => Ok(FunctionName::Scalar(ScalarFunctionName::Lower)),
+        "length" => Ok(FunctionName::Scalar(ScalarFunctionName::Length)),
+        "substr" => Ok(FunctionName::Scalar(ScalarFunctionName::Substr)),
+        "abs" => Ok(FunctionName::Scalar(ScalarFunctionName::Abs)),
+        "round" => Ok(FunctionName::Scalar(ScalarFunctionName::Round)),
+        "floor" => Ok(FunctionName::Scalar(ScalarFunctionName::Floor)),
+        "ceil" | "ceiling" => Ok(FunctionName::Scalar(ScalarFunctionName::Ceil)),
+        "mod" => Ok(FunctionName::Scalar(ScalarFunctionName::Mod)),
+        "now" => Ok(FunctionName::Scalar(ScalarFunctionName::Now)),
+        "date_part" => Ok(FunctionName::Scalar(ScalarFunctionName::DatePart)),
+        "strftime" => Ok(FunctionName::Scalar(ScalarFunctionName::Strftime)),
         _ => Err(FunctionParseError::UnknownFunctionName {
             ident: ident.to_string(),
         }),
     }
 }
 
+// `from a, b` is a cross join of each item in turn, the WHERE clause does
+// any filtering afterwards
 fn from_from(froms: &[ast::TableWithJoins]) -> Result<Query, ParseError> {
-    if let Some(table_with_joins) = froms.iter().next() {
-        let ast::TableWithJoins { relation, joins } = table_with_joins;
-        let from = from_relation(relation)?;
+    let mut queries = froms.iter().map(from_table_with_joins);
 
-        joins
-            .iter()
-            .try_fold(Query::From(from), |query, join| from_join(join, query))
-    } else {
-        Err(ParseError::EmptyFromNotSupported)
-    }
+    let first = queries.next().ok_or(ParseError::EmptyFromNotSupported)??;
+
+    queries.try_fold(first, |left, right| {
+        Ok(Query::CrossJoin(CrossJoin {
+            left_from: Box::new(left),
+            right_from: Box::new(right?),
+        }))
+    })
+}
+
+fn from_table_with_joins(table_with_joins: &ast::TableWithJoins) -> Result<Query, ParseError> {
+    let ast::TableWithJoins { relation, joins } = table_with_joins;
+    let from = from_relation(relation)?;
+
+    joins
+        .iter()
+        .try_fold(from, |query, join| from_join(join, query))
 }
 
 fn from_table_alias(table_alias: &ast::TableAlias) -> Result<TableAlias, ParseError> {
     if table_alias.columns.is_empty() {
-        Ok(TableAlias(table_alias.name.value.clone()))
+        Ok(TableAlias(table_alias.name.value.as_str().into()))
     } else {
         Err(ParseError::TableAliasColumnsNotSupported)
     }
 }
 
-fn from_relation(table: &ast::TableFactor) -> Result<From, ParseError> {
-    if let ast::TableFactor::Table {
-        name,
-        alias,
-        args: _,
-        with_hints: _,
-        version: _,
-        with_ordinality: _,
-        partitions: _,
-        json_path: _,
-        sample: _,
-        index_hints: _,
-    } = table
-    {
-        let table_name = table_name_from_object_name(name)?;
-
-        let table_alias = alias.as_ref().map(from_table_alias).transpose()?;
+// `tablesample bernoulli (10) repeatable (42)`. rows are all the same size
+// here, so `system` sampling is done row by row as well
+fn from_table_sample(from: Query, sample: &ast::TableSampleKind) -> Result<Query, ParseError> {
+    let (ast::TableSampleKind::BeforeTableAlias(sample)
+    | ast::TableSampleKind::AfterTableAlias(sample)) = sample;
+
+    let ast::TableSample {
+        modifier: ast::TableSampleModifier::TableSample,
+        name: None | Some(ast::TableSampleMethod::Bernoulli | ast::TableSampleMethod::System),
+        quantity:
+            Some(ast::TableSampleQuantity {
+                parenthesized: true,
+                value,
+                unit: None | Some(ast::TableSampleUnit::Percent),
+            }),
+        seed,
+        bucket: None,
+        offset: None,
+    } = sample.as_ref()
+    else {
+        return Err(ParseError::TableSampleNotSupported);
+    };
 
-        Ok(From {
-            table_name,
-            table_alias,
+    let percent = value_from_selection(value)?
+        .as_f64()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+        .ok_or_else(|| ParseError::InvalidSamplePercent {
+            percent: value.to_string(),
+        })?;
+
+    let seed = seed
+        .as_ref()
+        .map(|seed| {
+            seed.value
+                .to_string()
+                .parse()
+                .map_err(|_| ParseError::InvalidSampleSeed {
+                    seed: seed.value.to_string(),
+                })
         })
-    } else {
-        Err(ParseError::TableOnlyInFrom)
+        .transpose()?;
+
+    Ok(Query::Sample(Sample {
+        from: Box::new(from),
+        percent,
+        seed,
+    }))
+}
+
+fn from_relation(table: &ast::TableFactor) -> Result<Query, ParseError> {
+    match table {
+        ast::TableFactor::Table {
+            name,
+            alias,
+            args,
+            with_hints: _,
+            version: _,
+            with_ordinality: _,
+            partitions: _,
+            json_path: _,
+            sample,
+            index_hints: _,
+        } => {
+            let table_alias = alias.as_ref().map(from_table_alias).transpose()?;
+
+            // `generate_series(1, 10)` looks like a table with arguments
+            let query = if let Some(ast::TableFunctionArgs { args, settings: _ }) = args {
+                Query::TableFunction(TableFunctionCall {
+                    function_name: from_table_function_name(name)?,
+                    args: args
+                        .iter()
+                        .map(from_function_arg)
+                        .collect::<Result<_, _>>()?,
+                    table_alias,
+                })
+            } else {
+                Query::From(From {
+                    table_name: table_name_from_object_name(name)?,
+                    table_alias,
+                })
+            };
+
+            match sample {
+                Some(sample) => from_table_sample(query, sample),
+                None => Ok(query),
+            }
+        }
+        ast::TableFactor::UNNEST {
+            alias,
+            array_exprs,
+            with_offset,
+            with_offset_alias: _,
+            with_ordinality,
+        } => {
+            if *with_offset || *with_ordinality {
+                return Err(ParseError::UnnestOffsetNotSupported);
+            }
+
+            Ok(Query::TableFunction(TableFunctionCall {
+                function_name: TableFunctionName::Unnest,
+                args: array_exprs
+                    .iter()
+                    .map(from_selection)
+                    .collect::<Result<_, _>>()?,
+                table_alias: alias.as_ref().map(from_table_alias).transpose()?,
+            }))
+        }
+        _ => Err(ParseError::TableOnlyInFrom),
+    }
+}
+
+fn from_table_function_name(
+    object_name: &ast::ObjectName,
+) -> Result<TableFunctionName, ParseError> {
+    let TableName(name) = table_name_from_object_name(object_name)?;
+
+    match name.to_lowercase().as_str() {
+        "unnest" => Ok(TableFunctionName::Unnest),
+        "generate_series" => Ok(TableFunctionName::GenerateSeries),
+        _ => Err(ParseError::UnknownTableFunction { name }),
+    }
+}
+
+// the alias columns from this relation will be qualified with
+fn relation_alias(query: &Query) -> Option<TableAlias> {
+    match query {
+        Query::From(From { table_alias, .. })
+        | Query::TableFunction(TableFunctionCall { table_alias, .. }) => table_alias.clone(),
+        _ => None,
     }
 }
 
 fn from_join(join: &ast::Join, query: Query) -> Result<Query, ParseError> {
     let from = from_relation(&join.relation)?;
 
-    let (join_type, left_column_on, right_column_on) = from_join_operator(&join.join_operator)?;
+    // `a cross join b` is the same as `from a, b`
+    if let ast::JoinOperator::CrossJoin = join.join_operator {
+        return Ok(Query::CrossJoin(CrossJoin {
+            left_from: Box::new(query),
+            right_from: Box::new(from),
+        }));
+    }
 
-    let right_column_on = Column {
-        table_alias: from.table_alias.clone(),
-        ..right_column_on
+    let (join_type, constraint) = from_join_operator(&join.join_operator)?;
+
+    let on = match constraint {
+        ast::JoinConstraint::On(expr) => match from_join_on(expr, &from)? {
+            Some(on) => on,
+            // anything else is checked against every pair of rows
+            None => {
+                return Ok(Query::NestedLoopJoin(NestedLoopJoin {
+                    join_type,
+                    left_from: Box::new(query),
+                    right_from: Box::new(from),
+                    on: from_selection(expr)?,
+                }))
+            }
+        },
+        ast::JoinConstraint::Using(columns) => from_join_using(columns, &from)?,
+        _ => return Err(ParseError::from(JoinParseError::UnsupportedJoinConstraint)),
     };
 
     let join = Join {
         join_type,
         left_from: Box::new(query),
-        right_from: Box::new(Query::From(from)),
-        on: JoinOn {
-            left: left_column_on,
-            right: right_column_on,
-        },
+        right_from: Box::new(from),
+        on,
     };
 
     Ok(Query::Join(join))
 }
 
+// either `on a.id = b.id`, or `on id` for a column with the same name on both
+// sides, which can be hash joined. which side each column belongs to is
+// worked out against the schemas when the join runs. `None` means the join
+// is on some other expression
+fn from_join_on(expr: &ast::Expr, right_from: &Query) -> Result<Option<JoinOn>, ParseError> {
+    let is_identifier = |expr: &ast::Expr| {
+        matches!(
+            expr,
+            ast::Expr::Identifier(_) | ast::Expr::CompoundIdentifier(_)
+        )
+    };
+
+    match expr {
+        ast::Expr::Nested(expr) => from_join_on(expr, right_from),
+        ast::Expr::BinaryOp {
+            left,
+            op: ast::BinaryOperator::Eq,
+            right,
+        } if is_identifier(left) && is_identifier(right) => Ok(Some(JoinOn {
+            left: identifier_from_selection(left)?,
+            right: identifier_from_selection(right)?,
+        })),
+        _ if is_identifier(expr) => {
+            let identifier = identifier_from_selection(expr)?;
+
+            Ok(Some(JoinOn {
+                left: identifier.clone(),
+                right: Column {
+                    table_alias: relation_alias(right_from),
+                    ..identifier
+                },
+            }))
+        }
+        _ => Ok(None),
+    }
+}
+
+// `using (id)` is the same as `on id`, only one column is supported as
+// joins are on a single key
+fn from_join_using(columns: &[ast::ObjectName], right_from: &Query) -> Result<JoinOn, ParseError> {
+    let [name @ ast::ObjectName(parts)] = columns else {
+        return Err(ParseError::from(JoinParseError::UsingMultipleColumns));
+    };
+
+    let [ast::ObjectNamePart::Identifier(ident)] = parts.as_slice() else {
+        return Err(ParseError::ExpectedIdent {
+            found: name.to_string(),
+        });
+    };
+
+    let column = Column {
+        name: ident.value.as_str().into(),
+        table_alias: None,
+    };
+
+    Ok(JoinOn {
+        left: column.clone(),
+        right: Column {
+            table_alias: relation_alias(right_from),
+            ..column
+        },
+    })
+}
+
 fn from_join_operator(
     join_operator: &ast::JoinOperator,
-) -> Result<(JoinType, Column, Column), ParseError> {
-    let (join_type, constraint) = match join_operator {
+) -> Result<(JoinType, &ast::JoinConstraint), ParseError> {
+    match join_operator {
         ast::JoinOperator::Join(constraint) => Ok((JoinType::Inner, constraint)),
         ast::JoinOperator::LeftOuter(constraint) => Ok((JoinType::LeftOuter, constraint)),
-        _ => Err(JoinParseError::UnsupportedJoinOperator),
-    }?;
-
-    match constraint {
-        ast::JoinConstraint::On(expr) => {
-            let identifier = identifier_from_selection(expr)?;
-            Ok((join_type, identifier.clone(), identifier))
-        }
-        _ => Err(ParseError::from(JoinParseError::UnsupportedJoinConstraint)),
+        _ => Err(ParseError::from(JoinParseError::UnsupportedJoinOperator)),
     }
 }
 
@@ -607,7 +1889,7 @@ fn table_name_from_object_name(object_name: &ast::ObjectName) -> Result<TableNam
 
     if let Some(object_name_part) = object_name_parts.iter().next() {
         let ast::ObjectNamePart::Identifier(name) = object_name_part;
-        Ok(TableName(name.value.to_string()))
+        Ok(TableName(name.value.clone()))
     } else {
         Err(ParseError::EmptyObjectName)
     }
@@ -628,7 +1910,24 @@ fn from_projection(select_items: &[ast::SelectItem]) -> Result<Option<Vec<Expr>>
                 let expr = from_selection(expr)?;
                 fields.push(expr);
             }
-            _ => return Err(ParseError::UnsupportedProjectionField),
+            ast::SelectItem::ExprWithAlias { expr, alias } => {
+                fields.push(Expr::Alias {
+                    expr: Box::new(from_selection(expr)?),
+                    alias: alias.value.clone(),
+                });
+            }
+            ast::SelectItem::Wildcard(_) => fields.push(Expr::Wildcard { table_alias: None }),
+            ast::SelectItem::QualifiedWildcard(
+                ast::SelectItemQualifiedWildcardKind::ObjectName(object_name),
+                _,
+            ) => fields.push(Expr::Wildcard {
+                table_alias: Some(TableAlias(
+                    table_name_from_object_name(object_name)?.0.as_str().into(),
+                )),
+            }),
+            ast::SelectItem::QualifiedWildcard(..) => {
+                return Err(ParseError::UnsupportedProjectionField)
+            }
         }
     }
     Ok(Some(fields))
@@ -636,7 +1935,10 @@ fn from_projection(select_items: &[ast::SelectItem]) -> Result<Option<Vec<Expr>>
 
 #[cfg(test)]
 mod tests {
-    use crate::types::{Column, Expr, Filter, From, Join, JoinOn, JoinType, Op, Query, TableName};
+    use crate::types::{
+        Column, Expr, Filter, From, Join, JoinOn, JoinType, NestedLoopJoin, Op, Query, TableAlias,
+        TableName,
+    };
 
     use super::parse;
 
@@ -662,7 +1964,7 @@ mod tests {
             filter: Expr::BinaryOperation {
                 left: Box::new(Expr::Column {
                     column: Column {
-                        name: "album_id".to_string(),
+                        name: "album_id".into(),
                         table_alias: None,
                     },
                 }),
@@ -691,11 +1993,11 @@ mod tests {
                 })),
                 on: JoinOn {
                     left: Column {
-                        name: "species_id".to_string(),
+                        name: "species_id".into(),
                         table_alias: None,
                     },
                     right: Column {
-                        name: "species_id".to_string(),
+                        name: "species_id".into(),
                         table_alias: None,
                     },
                 },
@@ -703,7 +2005,7 @@ mod tests {
             filter: Expr::BinaryOperation {
                 left: Box::new(Expr::Column {
                     column: Column {
-                        name: "species_id".to_string(),
+                        name: "species_id".into(),
                         table_alias: None,
                     },
                 }),
@@ -725,4 +2027,136 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_parse_json_access_binds_tighter_than_comparison() {
+        let expected = Query::Filter(Filter {
+            from: Box::new(Query::From(From {
+                table_name: TableName("customer".into()),
+                table_alias: None,
+            })),
+            filter: Expr::BinaryOperation {
+                left: Box::new(Expr::BinaryOperation {
+                    left: Box::new(Expr::Column {
+                        column: Column {
+                            name: "meta".into(),
+                            table_alias: None,
+                        },
+                    }),
+                    op: Op::JsonGetText,
+                    right: Box::new(Expr::Literal {
+                        literal: "country".into(),
+                    }),
+                }),
+                op: Op::Equals,
+                right: Box::new(Expr::Literal {
+                    literal: "UK".into(),
+                }),
+            },
+        });
+
+        let result = parse("SELECT * FROM customer WHERE meta ->> 'country' = 'UK'").unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_array_index() {
+        let expected = Query::Filter(Filter {
+            from: Box::new(Query::From(From {
+                table_name: TableName("customer".into()),
+                table_alias: None,
+            })),
+            filter: Expr::BinaryOperation {
+                left: Box::new(Expr::Index {
+                    expr: Box::new(Expr::Column {
+                        column: Column {
+                            name: "tags".into(),
+                            table_alias: None,
+                        },
+                    }),
+                    index: Box::new(Expr::Literal { literal: 1.into() }),
+                }),
+                op: Op::Equals,
+                right: Box::new(Expr::Literal {
+                    literal: "vip".into(),
+                }),
+            },
+        });
+
+        let result = parse("SELECT * FROM customer WHERE tags[1] = 'vip'").unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_join_on_equality() {
+        let expected = Query::Join(Join {
+            join_type: JoinType::Inner,
+            left_from: Box::new(Query::From(From {
+                table_name: TableName("Album".to_string()),
+                table_alias: None,
+            })),
+            right_from: Box::new(Query::From(From {
+                table_name: TableName("Artist".to_string()),
+                table_alias: None,
+            })),
+            on: JoinOn {
+                left: Column {
+                    name: "ArtistId".into(),
+                    table_alias: Some(TableAlias("Artist".into())),
+                },
+                right: Column {
+                    name: "ArtistId".into(),
+                    table_alias: Some(TableAlias("Album".into())),
+                },
+            },
+        });
+
+        let result =
+            parse("SELECT * FROM Album JOIN Artist ON Artist.ArtistId = Album.ArtistId").unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_join_on_comparison() {
+        let column = |table: &str, name: &str| {
+            Box::new(Expr::Column {
+                column: Column {
+                    name: name.into(),
+                    table_alias: Some(TableAlias(table.into())),
+                },
+            })
+        };
+
+        let expected = Query::NestedLoopJoin(NestedLoopJoin {
+            join_type: JoinType::Inner,
+            left_from: Box::new(Query::From(From {
+                table_name: TableName("Album".to_string()),
+                table_alias: None,
+            })),
+            right_from: Box::new(Query::From(From {
+                table_name: TableName("Track".to_string()),
+                table_alias: None,
+            })),
+            on: Expr::BinaryOperation {
+                left: column("Track", "Milliseconds"),
+                op: Op::GreaterThan,
+                right: Box::new(Expr::BinaryOperation {
+                    left: column("Album", "AlbumId"),
+                    op: Op::Multiply,
+                    right: Box::new(Expr::Literal {
+                        literal: 1000.into(),
+                    }),
+                }),
+            },
+        });
+
+        let result =
+            parse("SELECT * FROM Album JOIN Track ON Track.Milliseconds > Album.AlbumId * 1000")
+                .unwrap();
+
+        assert_eq!(result, expected);
+    }
 }
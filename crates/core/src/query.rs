@@ -1,15 +1,41 @@
+mod bloom;
+mod date;
 mod filter;
 mod from;
+mod group_by;
 mod join;
+mod operator;
 mod order_by;
+mod parallel;
 mod project;
+mod reorder;
+mod sample;
+mod scalar_function;
+mod simplify;
+mod spill;
+mod subquery;
+mod table_function;
+pub mod tpch;
 
+use filter::Predicate;
+use operator::{
+    Blocking, BoxOperator, Context, DistinctRows, LimitRows, Measured, Operator, Rows, UnionRows,
+};
 use project::project_fields;
 
-use crate::types::{Limit, OrderBy};
+use std::num::NonZeroUsize;
+use std::rc::Rc;
+
+use crate::types::{
+    Cost, Expr, JoinType, Limit, OrderBy, Profile, Row, Sample, Schema, SchemaColumn, TableAlias,
+    TableFunctionCall, TableName,
+};
 
 use super::types::QueryStep;
-use super::types::{Column, Filter, From, Join, Project, Query};
+use super::types::{
+    Column, CrossJoin, Describe, Distinct, Explain, Filter, From, GroupBy, Join, NestedLoopJoin,
+    Project, Query, Union,
+};
 
 #[derive(Debug)]
 pub enum QueryError {
@@ -18,116 +44,553 @@ pub enum QueryError {
     FilterError(filter::FilterError),
     ArgumentNotFound,
     TypeMismatch { expected: String },
+    WrongNumberOfArguments { expected: usize, found: usize },
     CannotUseAggregateFunctionInFilter,
+    CannotNestAggregateFunctions,
+    ColumnMustBeAggregated { column_name: Column },
+    SubqueryNotSupportedHere,
+    SubqueryMustReturnOneColumn { found: usize },
+    UnionColumnCountMismatch { left: usize, right: usize },
+    DuplicateTableAlias { table_alias: TableAlias },
+    TableAliasNotFound { table_alias: TableAlias },
+    TableNotFound { table_name: TableName },
+    WildcardNotSupportedHere,
+    UnknownDatePart { field: String },
+    DivisionByZero,
+    ParameterNotBound { index: usize },
+    SpillFailed(std::io::Error),
+    LimitMustBeInt { value: serde_json::Value },
+}
+
+// settings for how queries are run, rather than what they return
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    // how many threads big scans, filters and hash join builds are split
+    // across. 1 runs everything on the calling thread
+    pub threads: usize,
+    // roughly how many bytes of rows a hash join can hold in memory before
+    // it spills to disk
+    pub join_memory_budget: usize,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            threads: std::thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            join_memory_budget: 256 * 1024 * 1024,
+        }
+    }
 }
 
 pub fn run_query(query: &Query) -> Result<QueryStep, QueryError> {
+    run_query_with_config(query, &EngineConfig::default())
+}
+
+pub fn run_query_with_config(
+    query: &Query,
+    config: &EngineConfig,
+) -> Result<QueryStep, QueryError> {
+    let mut query = query.clone();
+    reorder::cross_joins_to_joins(&mut query);
+    reorder::reorder_joins(&mut query);
+    simplify::simplify_filters(&mut query);
+
+    run_step(&query, &Context::new(config, None))
+}
+
+// run a query, calling `on_step` with the output of every operator in the
+// order they finish, so scans come first and the final result comes last
+pub fn run_query_with_trace(
+    query: &Query,
+    config: &EngineConfig,
+    on_step: &mut dyn FnMut(&QueryStep),
+) -> Result<QueryStep, QueryError> {
+    let mut query = query.clone();
+    reorder::cross_joins_to_joins(&mut query);
+    reorder::reorder_joins(&mut query);
+    simplify::simplify_filters(&mut query);
+
+    run_step(&query, &Context::new(config, Some(on_step)))
+}
+
+// build the operators for a query and pull every row out of the top one
+fn run_step<'a>(query: &'a Query, context: &Rc<Context<'a>>) -> Result<QueryStep, QueryError> {
+    let mut root = build(query, context)?;
+    let rows = root.next_batch(usize::MAX)?;
+    root.finish();
+
+    Ok(QueryStep {
+        schema: root.schema().clone(),
+        rows,
+        cost: context.cost(),
+        profile: root.stats().profile(),
+    })
+}
+
+// limits and offsets can't refer to any columns, so they are worked out
+// against an empty row
+fn limit_count(expr: &Expr) -> Result<usize, QueryError> {
+    let value = filter::evaluate_expr(&Row { items: vec![] }, &Schema::new(vec![]), expr)?;
+
+    value
+        .as_u64()
+        .and_then(|count| usize::try_from(count).ok())
+        .ok_or(QueryError::LimitMustBeInt { value })
+}
+
+// the columns a `from` clause gives, qualified the same way its scans
+// qualify them, worked out without running it. the parser uses them to tell a
+// column apart from a select-list alias of the same name, and planning uses
+// them to tell which side of a cross join a column is on. tables that don't
+// exist have none, and running the query reports them
+pub fn from_columns(query: &Query) -> Vec<Column> {
+    let qualified = |columns: Vec<Column>, table_alias: TableAlias| {
+        columns
+            .into_iter()
+            .map(|column| Column {
+                table_alias: Some(table_alias.clone()),
+                ..column
+            })
+            .collect()
+    };
+
     match query {
         Query::From(From {
             table_name,
             table_alias,
-        }) => Ok(from::table_scan(table_name, table_alias.as_ref())),
+        }) => {
+            if from::TABLE_NAMES.contains(&table_name.0.as_str()) {
+                qualified(
+                    from::schema(table_name),
+                    table_alias
+                        .clone()
+                        .unwrap_or_else(|| TableAlias(table_name.0.as_str().into())),
+                )
+            } else {
+                vec![]
+            }
+        }
+        Query::TableFunction(TableFunctionCall {
+            function_name,
+            table_alias,
+            ..
+        }) => qualified(
+            table_function::table_function_columns(function_name),
+            table_alias
+                .clone()
+                .unwrap_or_else(|| TableAlias(function_name.to_string().into())),
+        ),
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        }) => [from_columns(left_from), from_columns(right_from)].concat(),
+        Query::Filter(Filter { from, .. }) | Query::Sample(Sample { from, .. }) => {
+            from_columns(from)
+        }
+        _ => vec![],
+    }
+}
+
+// one row per operator, indented under the operator it feeds. joins have
+// already been reordered by `run_query_with_trace`, so this is the plan that
+// would actually run
+fn explain(query: &Query) -> QueryStep {
+    fn add_rows(query: &Query, depth: usize, rows: &mut Vec<Row>) {
+        rows.push(Row {
+            items: vec![format!(
+                "{:indent$}{}",
+                "",
+                describe_operator(query),
+                indent = depth * 2
+            )
+            .into()],
+        });
+
+        for input in inputs(query) {
+            add_rows(input, depth + 1, rows);
+        }
+    }
+
+    let mut rows = vec![];
+    add_rows(query, 0, &mut rows);
+
+    QueryStep {
+        schema: Schema::new(vec![SchemaColumn::Named("plan".into())]),
+        rows,
+        cost: Cost::new(),
+        profile: Profile::default(),
+    }
+}
+
+// the queries whose output an operator reads, in the order it reads them
+fn inputs(query: &Query) -> Vec<&Query> {
+    match query {
+        Query::From(_) | Query::TableFunction(_) | Query::Describe(_) | Query::ShowTables => {
+            vec![]
+        }
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => vec![left_from, right_from],
+        Query::Filter(Filter { from, .. })
+        | Query::Project(Project { from, .. })
+        | Query::GroupBy(GroupBy { from, .. })
+        | Query::Sample(Sample { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::Limit(Limit { from, .. })
+        | Query::OrderBy(OrderBy { from, .. })
+        | Query::Explain(Explain { query: from }) => vec![from],
+    }
+}
+
+fn operator_name(query: &Query) -> &'static str {
+    match query {
+        Query::From(_) => "from",
+        Query::TableFunction(_) => "table_function",
+        Query::Filter(_) => "filter",
+        Query::Sample(_) => "sample",
+        Query::Join(_) => "join",
+        Query::CrossJoin(_) => "cross_join",
+        Query::NestedLoopJoin(_) => "nested_loop_join",
+        Query::Describe(_) => "describe",
+        Query::ShowTables => "show_tables",
+        Query::Explain(_) => "explain",
+        Query::Project(_) => "project",
+        Query::GroupBy(_) => "group_by",
+        Query::Distinct(_) => "distinct",
+        Query::Union(_) => "union_all",
+        Query::Limit(_) => "limit",
+        Query::OrderBy(_) => "order_by",
+    }
+}
+
+// operator name with enough detail to tell nodes apart in a profile
+fn describe_operator(query: &Query) -> String {
+    let name = operator_name(query);
+    match query {
+        Query::From(From {
+            table_name,
+            table_alias,
+        }) => match table_alias {
+            Some(table_alias) => format!("{name} {} as {table_alias}", table_name.0),
+            None => format!("{name} {}", table_name.0),
+        },
+        Query::TableFunction(TableFunctionCall {
+            function_name,
+            table_alias,
+            ..
+        }) => match table_alias {
+            Some(table_alias) => format!("{name} {function_name} as {table_alias}"),
+            None => format!("{name} {function_name}"),
+        },
+        Query::Join(Join {
+            join_type,
+            on,
+            left_from,
+            right_from,
+        }) => {
+            // building on the right is what usually happens, so only the
+            // other way round is worth pointing out
+            let build_side = match join::build_side(left_from, right_from) {
+                join::BuildSide::Left => " build left",
+                join::BuildSide::Right => "",
+            };
+            format!(
+                "{name} {} on {} = {}{build_side}",
+                describe_join_type(join_type),
+                on.left,
+                on.right
+            )
+        }
+        Query::NestedLoopJoin(NestedLoopJoin { join_type, .. }) => {
+            format!("{name} {}", describe_join_type(join_type))
+        }
+        Query::Sample(Sample { percent, seed, .. }) => match seed {
+            Some(seed) => format!("{name} {percent}% seed {seed}"),
+            None => format!("{name} {percent}%"),
+        },
+        Query::Limit(Limit { limit, offset, .. }) => {
+            let describe = |expr: &Expr| match expr {
+                Expr::Literal { literal } => literal.to_string(),
+                _ => "expression".to_string(),
+            };
+            let limit = limit.as_ref().map(|limit| format!(" {}", describe(limit)));
+            let offset = offset
+                .as_ref()
+                .map(|offset| format!(" offset {}", describe(offset)));
+            format!(
+                "{name}{}{}",
+                limit.unwrap_or_default(),
+                offset.unwrap_or_default()
+            )
+        }
+        Query::Describe(Describe { table_name }) => format!("{name} {}", table_name.0),
+        // the `()` grouping set of a rollup groups on nothing
+        Query::GroupBy(GroupBy { group_by, .. }) if group_by.is_empty() => name.to_string(),
+        Query::GroupBy(GroupBy { group_by, .. }) => {
+            let group_by = group_by
+                .iter()
+                .map(|expr| match expr {
+                    Expr::Column { column } => column.to_string(),
+                    _ => "expression".to_string(),
+                })
+                .collect::<Vec<_>>();
+            format!("{name} {}", group_by.join(", "))
+        }
+        Query::Filter(_)
+        | Query::ShowTables
+        | Query::Explain(_)
+        | Query::CrossJoin(_)
+        | Query::Project(_)
+        | Query::Distinct(_)
+        | Query::Union(_)
+        | Query::OrderBy(_) => name.to_string(),
+    }
+}
+
+fn describe_join_type(join_type: &JoinType) -> &'static str {
+    match join_type {
+        JoinType::Inner => "inner",
+        JoinType::LeftOuter => "left outer",
+    }
+}
+
+// turn each node of the plan into an operator, wrapped so that its rows and
+// time are counted
+fn build<'a>(query: &'a Query, context: &Rc<Context<'a>>) -> Result<Measured<'a>, QueryError> {
+    let mut children = vec![];
+    let mut input = |query: &'a Query| -> Result<BoxOperator<'a>, QueryError> {
+        let input = build(query, context)?;
+        children.push(input.stats());
+        Ok(Box::new(input))
+    };
+
+    let operator: BoxOperator<'a> = match query {
+        Query::From(From {
+            table_name,
+            table_alias,
+        }) => Box::new(from::table_scan(table_name, table_alias.as_ref(), context)?),
+        Query::Describe(Describe { table_name }) => {
+            Box::new(Rows::from_step(from::describe(table_name)?, context))
+        }
+        Query::ShowTables => Box::new(Rows::from_step(from::show_tables(), context)),
+        Query::Explain(Explain { query }) => Box::new(Rows::from_step(explain(query), context)),
+        Query::TableFunction(table_function_call) => Box::new(table_function::table_function_scan(
+            table_function_call,
+            context,
+        )?),
         Query::Filter(Filter { from, filter }) => {
-            let QueryStep {
-                schema,
-                rows,
-                mut cost,
-            } = run_query(from)?;
+            let from = input(from)?;
 
-            let mut filtered_rows = vec![];
+            // aggregates need all the rows, so they make no sense per row
+            if project::is_aggregate_expr(filter) {
+                return Err(QueryError::CannotUseAggregateFunctionInFilter);
+            }
 
-            for row in rows {
-                cost.increment_rows_processed();
-                if filter::apply_predicate(&row, &schema, filter)? {
-                    filtered_rows.push(row);
+            let predicate = match filter {
+                // `x in (select ...)` runs the subquery once and checks each
+                // row against a set of its values, rather than running it per
+                // row
+                Expr::InSubquery {
+                    expr,
+                    subquery,
+                    negated,
+                } => {
+                    let mut semi_join = join::SemiJoin::new(expr, input(subquery)?, *negated)?;
+                    Predicate::Row(Box::new(move |row, schema| semi_join.keeps(row, schema)))
                 }
-            }
+                // `x > all (select ...)` is the same, with the values
+                // compared like an array of them
+                Expr::QuantifiedSubquery {
+                    left,
+                    op,
+                    quantifier,
+                    subquery,
+                } => {
+                    let mut quantified =
+                        join::QuantifiedSubquery::new(left, op, quantifier, input(subquery)?)?;
+                    Predicate::Row(Box::new(move |row, schema| quantified.keeps(row, schema)))
+                }
+                Expr::Exists { subquery, negated } => {
+                    let mut exists = subquery::Exists::new(subquery, *negated);
+                    let context = context.clone();
+                    Predicate::Row(Box::new(move |row, schema| {
+                        exists.keeps(row, schema, &context)
+                    }))
+                }
+                // a predicate that doesn't mention any columns, like `1 = 1`,
+                // is the same for every row, so work it out once and keep or
+                // drop them all
+                _ if project::unaggregated_column(filter, &[]).is_none() => {
+                    let empty_row = Row { items: vec![] };
+                    Predicate::Constant(filter::apply_predicate(&empty_row, from.schema(), filter)?)
+                }
+                _ => Predicate::Expr(filter),
+            };
 
-            Ok(QueryStep {
-                schema,
-                rows: filtered_rows,
-                cost,
-            })
+            Box::new(filter::FilterRows::new(from, predicate, context))
         }
         Query::Project(Project { from, fields }) => {
-            let QueryStep {
-                schema,
-                rows,
-                mut cost,
-            } = run_query(from)?;
+            let from = input(from)?;
 
-            let projected_rows = project_fields(&rows, &schema, fields, &mut cost)?;
+            let fields = project::expand_wildcards(fields, from.schema())?;
+            let schema = project::project_schema(from.schema(), &fields)?;
 
-            let schema = project::project_schema(&schema, fields)?;
+            // aggregates are worked out over every row at once
+            if fields.iter().any(project::is_aggregate_expr) {
+                let from_schema = from.schema().clone();
+                Box::new(Blocking::new(
+                    from,
+                    schema,
+                    move |rows, cost| project_fields(&rows, &from_schema, &fields, cost),
+                    context,
+                ))
+            } else {
+                Box::new(project::ProjectRows::new(from, fields, schema, context))
+            }
+        }
+        Query::GroupBy(GroupBy {
+            from,
+            group_by,
+            having,
+            fields,
+        }) => {
+            let from = input(from)?;
+
+            let fields = project::expand_wildcards(fields, from.schema())?;
+            let schema = project::project_schema(from.schema(), &fields)?;
 
-            Ok(QueryStep {
+            let from_schema = from.schema().clone();
+            Box::new(Blocking::new(
+                from,
                 schema,
-                rows: projected_rows,
-                cost,
-            })
+                move |rows, cost| {
+                    group_by::group_by(rows, &from_schema, group_by, having.as_ref(), &fields, cost)
+                },
+                context,
+            ))
         }
-        Query::Limit(Limit { limit, from }) => {
-            let QueryStep {
-                schema,
-                mut rows,
-                cost,
-            } = run_query(from)?;
-            let size: usize = (*limit).try_into().unwrap();
-
-            rows.truncate(size);
+        Query::Sample(Sample {
+            from,
+            percent,
+            seed,
+        }) => Box::new(sample::SampleRows::new(
+            input(from)?,
+            *percent,
+            *seed,
+            context,
+        )),
+        Query::Distinct(Distinct { from }) => Box::new(DistinctRows::new(input(from)?, context)),
+        Query::Union(Union { left, right }) => {
+            Box::new(UnionRows::new(input(left)?, input(right)?)?)
+        }
+        Query::Limit(Limit {
+            limit,
+            offset,
+            from,
+        }) => {
+            let offset = offset.as_ref().map(limit_count).transpose()?;
+            let limit = limit.as_ref().map(limit_count).transpose()?;
 
-            Ok(QueryStep { schema, rows, cost })
+            Box::new(LimitRows::new(input(from)?, limit, offset))
         }
         Query::Join(Join {
             left_from,
             right_from,
             join_type,
             on,
+        }) => Box::new(join::HashJoin::new(
+            input(left_from)?,
+            input(right_from)?,
+            on,
+            join_type,
+            join::build_side(left_from, right_from),
+            context,
+        )?),
+        Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        }) => Box::new(join::CrossJoinRows::new(
+            input(left_from)?,
+            input(right_from)?,
+            context,
+        )?),
+        Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            join_type,
+            on,
         }) => {
-            let QueryStep {
-                schema: left_schema,
-                rows: left_rows,
-                cost: mut left_cost,
-            } = run_query(left_from)?;
-
-            let QueryStep {
-                schema: right_schema,
-                rows: right_rows,
-                cost: right_cost,
-            } = run_query(right_from)?;
-
-            left_cost.extend(&right_cost);
-
-            join::hash_join(
-                left_rows,
-                &left_schema,
-                right_rows,
-                &right_schema,
+            // like a filter, the condition is checked one pair at a time
+            if project::is_aggregate_expr(on) {
+                return Err(QueryError::CannotUseAggregateFunctionInFilter);
+            }
+
+            Box::new(join::NestedLoopJoinRows::new(
+                input(left_from)?,
+                input(right_from)?,
                 on,
                 join_type,
-                left_cost,
-            )
+                context,
+            )?)
         }
         Query::OrderBy(OrderBy {
             from,
             order_by_exprs,
         }) => {
-            let QueryStep {
-                schema,
-                rows,
-                mut cost,
-            } = run_query(from)?;
-
-            let rows = order_by::order_by(rows, &schema, order_by_exprs, &mut cost);
+            let from = input(from)?;
+            let schema = from.schema().clone();
 
-            Ok(QueryStep { schema, rows, cost })
+            Box::new(Blocking::new(
+                from,
+                schema.clone(),
+                move |rows, cost| order_by::order_by(rows, &schema, order_by_exprs, cost),
+                context,
+            ))
         }
-    }
+    };
+
+    Ok(Measured::new(
+        operator,
+        operator_name(query),
+        describe_operator(query),
+        children,
+        context,
+    ))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parser::parse, run_query};
+    use super::{
+        filter, run_query_with_config, run_query_with_trace, tpch, EngineConfig, QueryError,
+    };
+    use crate::parser::{parse, ParseError};
+    use crate::types::{Cost, Filter, Query};
+    use crate::{parse_with_parameters, run_query};
 
     #[test]
     fn test_query_select_animals() {
@@ -139,6 +602,54 @@ mod tests {
         insta::assert_debug_snapshot!(result.cost);
     }
 
+    #[test]
+    fn test_query_csv_output() {
+        let query = parse("select customer_name, active, meta from customer").unwrap();
+        let result = run_query(&query).unwrap();
+
+        let mut csv = vec![];
+        result.write_csv(&mut csv).unwrap();
+
+        insta::assert_snapshot!(String::from_utf8(csv).unwrap());
+    }
+
+    #[test]
+    fn test_query_constant_filter() {
+        let query = parse("select animal_name from animal where 1 = 1").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+
+        let query = parse("select animal_name from animal where 1 = 2").unwrap();
+        let result = run_query(&query).unwrap();
+
+        assert!(result.rows.is_empty());
+    }
+
+    #[test]
+    fn test_query_contradictory_filter() {
+        // no track can be both, so the table is never read
+        let query =
+            parse("select Name from Track where Milliseconds > 5 and Milliseconds < 3").unwrap();
+        let result = run_query(&query).unwrap();
+
+        assert!(result.rows.is_empty());
+        assert_eq!(result.cost.rows_processed, 0);
+
+        let merged = parse(
+            "select Name from Track where TrackId > 5 and TrackId >= 10 and 1 = 1 and 12 > TrackId",
+        )
+        .unwrap();
+        let written_once =
+            parse("select Name from Track where TrackId >= 10 and TrackId < 12").unwrap();
+
+        assert_eq!(
+            run_query(&merged).unwrap().to_json(),
+            run_query(&written_once).unwrap().to_json()
+        );
+    }
+
     #[test]
     fn test_query_select_horse() {
         let query = parse("select * from animal where animal_name = 'horse'").unwrap();
@@ -207,6 +718,36 @@ mod tests {
         insta::assert_debug_snapshot!(result.cost);
     }
 
+    #[test]
+    fn test_join_skips_null_keys() {
+        let query = parse(
+            r"
+        select animal.animal_name, other.animal_name from animal
+          join animal as other on species_id
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_left_outer_join_pads_null_keys() {
+        let query = parse(
+            r"
+        select * from animal
+          left outer join species on species_id
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
     #[test]
     fn test_select_album() {
         let query = parse(
@@ -258,6 +799,23 @@ mod tests {
         insta::assert_debug_snapshot!(result.cost);
     }
 
+    #[test]
+    fn test_select_columns_qualified_by_table_name() {
+        let query = parse(
+            r"
+        select Album.Title, Artist.ArtistId, Artist.Name from Album
+          join Artist on ArtistId
+        where
+          Album.ArtistId = 1
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
     #[test]
     fn test_select_filter_with_column_reference() {
         let query = parse(
@@ -305,6 +863,516 @@ mod tests {
         insta::assert_debug_snapshot!(result.cost);
     }
 
+    #[test]
+    fn test_select_order_by_column_not_in_projection() {
+        let query = parse(
+            r"
+        select Title from Album
+        order by ArtistId desc, Title
+        limit 3
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_order_by_ties_keep_input_order() {
+        let query = parse(
+            r"
+        select AlbumId, ArtistId from Album
+        order by ArtistId
+        limit 6
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_join_output_order_is_stable() {
+        let sql = r"
+        select Album.Title, Track.Name from Album
+          join Track on AlbumId
+        where
+          Album.ArtistId = 1
+    ";
+        let first = run_query(&parse(sql).unwrap()).unwrap().to_json();
+
+        for _ in 0..5 {
+            let again = run_query(&parse(sql).unwrap()).unwrap().to_json();
+            assert_eq!(first, again);
+        }
+
+        insta::assert_json_snapshot!(first);
+    }
+
+    #[test]
+    fn test_profile_follows_plan() {
+        let query = parse(
+            r"
+        select Title from Album
+          join Artist on ArtistId
+        where
+          ArtistId = 1
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        let profile = &result.profile;
+        assert_eq!(profile.operator, "project");
+        assert_eq!((profile.rows_in, profile.rows_out), (2, 2));
+
+        let filter = &profile.children[0];
+        assert_eq!(filter.operator, "filter");
+        assert_eq!((filter.rows_in, filter.rows_out), (347, 2));
+
+        let join = &filter.children[0];
+        assert_eq!(join.operator, "join inner on ArtistId = ArtistId");
+        assert_eq!((join.rows_in, join.rows_out), (347 + 275, 347));
+
+        let scans: Vec<_> = join
+            .children
+            .iter()
+            .map(|child| (child.operator.as_str(), child.rows_out))
+            .collect();
+        assert_eq!(scans, vec![("from Album", 347), ("from Artist", 275)]);
+        assert!(join.memory_bytes > 0);
+    }
+
+    #[test]
+    fn test_limit_stops_scan_early() {
+        let query = parse("select Name from Track limit 10").unwrap();
+        let result = run_query(&query).unwrap();
+
+        let mut profile = &result.profile;
+        while let Some(child) = profile.children.first() {
+            profile = child;
+        }
+
+        assert_eq!(profile.operator, "from Track");
+        assert_eq!(profile.rows_out, 10);
+        assert_eq!(result.cost.rows_processed, 20);
+    }
+
+    #[test]
+    fn test_trace_visits_operators_in_execution_order() {
+        let query = parse(
+            r"
+        select Title from Album
+          join Artist on ArtistId
+        where
+          ArtistId = 1
+    ",
+        )
+        .unwrap();
+
+        let mut steps = vec![];
+        let result = run_query_with_trace(&query, &EngineConfig::default(), &mut |step| {
+            steps.push((step.profile.operator.clone(), step.rows.len()));
+        })
+        .unwrap();
+
+        // a join reads all of its right side before it streams its left
+        assert_eq!(
+            steps,
+            vec![
+                ("from Artist".to_string(), 275),
+                ("from Album".to_string(), 347),
+                ("join inner on ArtistId = ArtistId".to_string(), 347),
+                ("filter".to_string(), 2),
+                ("project".to_string(), 2),
+            ]
+        );
+        assert_eq!(result.rows.len(), 2);
+    }
+
+    #[test]
+    fn test_join_chain_starts_from_smallest_table() {
+        let query = parse(
+            r"
+        select Name, Title from Track
+          join Album on AlbumId
+          join Artist as artist on ArtistId
+    ",
+        )
+        .unwrap();
+
+        let mut steps = vec![];
+        run_query_with_trace(&query, &EngineConfig::default(), &mut |step| {
+            steps.push((step.profile.operator.clone(), step.rows.len()));
+        })
+        .unwrap();
+
+        assert_eq!(
+            steps,
+            vec![
+                ("from Artist as artist".to_string(), 275),
+                ("from Album".to_string(), 347),
+                (
+                    "join inner on Album.ArtistId = artist.ArtistId build left".to_string(),
+                    347
+                ),
+                ("from Track".to_string(), 3503),
+                (
+                    "join inner on Track.AlbumId = Album.AlbumId build left".to_string(),
+                    3503
+                ),
+                ("project".to_string(), 3503),
+                ("project".to_string(), 3503),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_partitioned_filter_matches_single_partition() {
+        let query = parse("select * from lineitem").unwrap();
+        let lineitem = run_query(&query).unwrap();
+        let query = parse("select * from lineitem where quantity >= 50").unwrap();
+        let Query::Filter(Filter {
+            filter: predicate, ..
+        }) = query
+        else {
+            panic!("expected a filter")
+        };
+
+        let filter_with = |partitions| {
+            let mut cost = Cost::new();
+            let rows = filter::filter_partitioned(
+                lineitem.rows.clone(),
+                &lineitem.schema,
+                &predicate,
+                &mut cost,
+                partitions,
+            )
+            .unwrap();
+            (rows, cost.rows_processed)
+        };
+
+        let (expected, expected_cost) = filter_with(1);
+        assert!(!expected.is_empty());
+
+        for partitions in [2, 3, 8] {
+            let (rows, cost) = filter_with(partitions);
+            assert_eq!(rows, expected);
+            assert_eq!(cost, expected_cost);
+        }
+    }
+
+    #[test]
+    fn test_threads_give_same_result() {
+        let query = parse(
+            r"
+        select * from lineitem
+          join orders on orderkey
+        where
+          quantity >= 40
+    ",
+        )
+        .unwrap();
+
+        let run_with = |threads| {
+            let config = EngineConfig {
+                threads,
+                ..EngineConfig::default()
+            };
+            run_query_with_config(&query, &config).unwrap()
+        };
+
+        let expected = run_with(1);
+        assert!(!expected.rows.is_empty());
+
+        for threads in [2, 3, 8] {
+            let result = run_with(threads);
+            assert_eq!(result.rows, expected.rows);
+            assert_eq!(result.cost.rows_processed, expected.cost.rows_processed);
+        }
+    }
+
+    #[test]
+    fn test_join_spills_past_memory_budget() {
+        let query = parse(
+            r"
+        select TrackId, Title from Track
+          left outer join Album on AlbumId
+        order by TrackId
+    ",
+        )
+        .unwrap();
+
+        let in_memory = run_query(&query).unwrap();
+        assert_eq!(in_memory.cost.spilled_rows, 0);
+
+        let config = EngineConfig {
+            join_memory_budget: 1000,
+            ..EngineConfig::default()
+        };
+        let spilled = run_query_with_config(&query, &config).unwrap();
+
+        assert_eq!(spilled.rows, in_memory.rows);
+        assert_eq!(spilled.cost.spilled_rows, 3503 + 347);
+        assert!(spilled.cost.spilled_bytes > 0);
+    }
+
+    #[test]
+    fn test_comma_join_becomes_hash_join() {
+        // the where clause equality joins the tables, rather than filtering
+        // every pair of them
+        let query =
+            parse("explain select * from Album, Artist where Album.ArtistId = Artist.ArtistId")
+                .unwrap();
+        insta::assert_json_snapshot!(run_query(&query).unwrap().to_json());
+
+        let query = parse(
+            r"
+        explain select Track.Name from Track, Album, Artist
+        where Track.AlbumId = Album.AlbumId
+          and Album.ArtistId = Artist.ArtistId
+          and Artist.Name = 'AC/DC'
+    ",
+        )
+        .unwrap();
+        insta::assert_json_snapshot!(run_query(&query).unwrap().to_json());
+
+        let query =
+            parse("select count(*) from Track, Album where Track.AlbumId = Album.AlbumId").unwrap();
+        let result = run_query(&query).unwrap();
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_join_builds_on_smaller_side() {
+        // there are fewer artists than albums, so the artists are built on,
+        // and the ones without albums are padded once the albums run out
+        let hash_join = parse(
+            r"
+        select Artist.ArtistId, AlbumId from Artist
+          left outer join Album on ArtistId
+        order by Artist.ArtistId, AlbumId
+    ",
+        )
+        .unwrap();
+
+        // the same join, run by checking every pair instead
+        let nested_loop = parse(
+            r"
+        select Artist.ArtistId, AlbumId from Artist
+          left outer join Album on Artist.ArtistId = Album.ArtistId + 0
+        order by Artist.ArtistId, AlbumId
+    ",
+        )
+        .unwrap();
+        let expected = run_query(&nested_loop).unwrap();
+
+        let in_memory = run_query(&hash_join).unwrap();
+        assert_eq!(in_memory.rows, expected.rows);
+
+        let config = EngineConfig {
+            join_memory_budget: 1000,
+            ..EngineConfig::default()
+        };
+        let spilled = run_query_with_config(&hash_join, &config).unwrap();
+        assert!(spilled.cost.spilled_rows > 0);
+        assert_eq!(spilled.rows, expected.rows);
+    }
+
+    #[test]
+    fn test_join_bloom_filter_skips_probe_rows() {
+        // only a few orders belong to the five customers, so the build
+        // side's keys let the orders scan drop most rows before the join
+        // sees them
+        let query = parse(
+            r"
+        select customer_name, orderkey from customer
+          join orders on customer_id = custkey
+    ",
+        )
+        .unwrap();
+
+        let mut orders_scanned = None;
+        let result = run_query_with_trace(&query, &EngineConfig::default(), &mut |step| {
+            if step.profile.operator == "from orders" {
+                orders_scanned = Some(step.rows.len());
+            }
+        })
+        .unwrap();
+
+        assert_eq!(result.rows.len(), 46);
+        assert_eq!(
+            orders_scanned,
+            Some(1500 - usize::try_from(result.cost.bloom_filtered_rows).unwrap())
+        );
+        assert!(result.cost.bloom_filtered_rows > 1400);
+    }
+
+    #[test]
+    fn test_duplicate_aggregate_names() {
+        let query = parse("select count(AlbumId), count(ArtistId) from Album").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+    }
+
+    #[test]
+    fn test_duplicate_expression_names() {
+        let query = parse("select AlbumId + 1, ArtistId + 1 from Album limit 2").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+    }
+
+    #[test]
+    fn test_same_column_twice() {
+        let query = parse("select Name, Name from Artist limit 2").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+    }
+
+    #[test]
+    fn test_column_aliases() {
+        let query = parse(
+            "select Title as album_title, AlbumId - 1 as previous from Album order by album_title limit 3",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+    }
+
+    #[test]
+    fn test_in_subquery() {
+        let query = parse(
+            r"
+        select Title from Album
+        where ArtistId in (select ArtistId from Artist where Name like 'AC%')
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_correlated_exists() {
+        let query = parse(
+            r"
+        select species_name from species
+        where not exists (
+            select animal_id from animal where animal.species_id = species.species_id
+        )
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_union() {
+        let query = parse(
+            r"
+        select Name from Artist where ArtistId <= 2
+        union
+        select Name from Artist where ArtistId <= 3
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_select_distinct() {
+        let query = parse("select distinct GenreId from Track where AlbumId <= 10").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_avg_aggregate() {
+        let query = parse("select avg(Milliseconds), avg(UnitPrice) - 0.5 from Track").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+    }
+
+    #[test]
+    fn test_group_by() {
+        let query = parse(
+            r"
+        select ArtistId, count(AlbumId), array_agg(Title) from Album
+        where ArtistId <= 3
+        group by ArtistId
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_group_by_having() {
+        let query = parse(
+            r"
+        select AlbumId, count(TrackId) from Track
+        group by AlbumId
+        having sum(Milliseconds) > 5000000
+    ",
+        )
+        .unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+        insta::assert_debug_snapshot!(result.cost);
+    }
+
+    #[test]
+    fn test_count_star() {
+        let query = parse("select count(*) from Album").unwrap();
+        let result = run_query(&query).unwrap();
+
+        insta::assert_json_snapshot!(result.to_json());
+    }
+
+    #[test]
+    fn test_group_by_column_must_be_grouped() {
+        let query = parse("select Title, count(AlbumId) from Album group by ArtistId").unwrap();
+
+        assert!(matches!(
+            run_query(&query),
+            Err(QueryError::ColumnMustBeAggregated { .. })
+        ));
+    }
+
+    #[test]
+    fn test_benchmark_queries() {
+        for (name, sql) in tpch::BENCHMARK_QUERIES {
+            let result = run_query(&parse(sql).unwrap()).unwrap();
+
+            insta::assert_json_snapshot!(format!("benchmark_{name}"), result.to_json());
+            insta::assert_debug_snapshot!(format!("benchmark_{name}_cost"), result.cost);
+        }
+    }
+
     #[test]
     fn test_single_row_aggregate() {
         let query = parse(
@@ -327,9 +1395,94 @@ mod tests {
     ",
         )
         .unwrap();
+        let result = run_query(&query);
+
+        assert!(matches!(
+            result,
+            Err(QueryError::ColumnMustBeAggregated { column_name }) if &*column_name.name == "Name"
+        ));
+    }
+
+    #[test]
+    fn test_unknown_table() {
+        for sql in [
+            "describe nosuchtable",
+            "show columns from nosuchtable",
+            "select * from nosuchtable",
+            "select * from animal join nosuchtable on animal_id",
+        ] {
+            let error = run_query(&parse(sql).unwrap()).err();
+
+            assert!(
+                matches!(
+                    &error,
+                    Some(QueryError::TableNotFound { table_name }) if table_name.0 == "nosuchtable"
+                ),
+                "{sql}: {error:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_order_by_unknown_column() {
+        let query = parse("select Title from Album order by nosuch").unwrap();
+        insta::assert_debug_snapshot!(run_query(&query).err());
+
+        // grouping only keeps the grouped and aggregated columns
+        let query =
+            parse("select ArtistId, count(*) from Album group by ArtistId order by Title").unwrap();
+        insta::assert_debug_snapshot!(run_query(&query).err());
+
+        // a union's columns are named after its first query's
+        let query =
+            parse("select Name from Artist union select Title from Album order by Title").unwrap();
+        insta::assert_debug_snapshot!(run_query(&query).err());
+    }
+
+    #[test]
+    fn test_aggregate_in_filter() {
+        let query = parse("select * from animal where sum(animal_id) = 10").unwrap();
+        let result = run_query(&query);
+
+        assert!(matches!(
+            result,
+            Err(QueryError::CannotUseAggregateFunctionInFilter)
+        ));
+    }
+
+    #[test]
+    fn test_limit_parameters() {
+        let query = parse_with_parameters(
+            "select animal_name from animal where animal_id > $2 limit $1",
+            &[2.into(), 1.into()],
+        )
+        .unwrap();
         let result = run_query(&query).unwrap();
 
-        insta::assert_json_snapshot!(result.to_json());
-        insta::assert_debug_snapshot!(result.cost);
+        assert_eq!(
+            result
+                .rows
+                .into_iter()
+                .map(|row| row.items)
+                .collect::<Vec<_>>(),
+            vec![
+                vec![serde_json::json!("dog")],
+                vec![serde_json::json!("snake")]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbound_parameter() {
+        let query = parse("select animal_name from animal limit $1").unwrap();
+
+        assert!(matches!(
+            run_query(&query),
+            Err(QueryError::ParameterNotBound { index: 1 })
+        ));
+        assert!(matches!(
+            parse_with_parameters("select animal_name from animal limit $2", &[1.into()]),
+            Err(ParseError::ParameterNotGiven { index: 2 })
+        ));
     }
 }
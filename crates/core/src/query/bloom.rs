@@ -0,0 +1,116 @@
+use std::cell::OnceCell;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use super::filter::distinct_key;
+use crate::types::Column;
+
+// a set of values that can say for certain that a value isn't in it, but
+// only that one probably is. each value sets a few bits picked by its hash,
+// and a value whose bits aren't all set can't have been added
+pub struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+// ten bits and seven hashes per value gives about one false positive in a
+// hundred
+const BITS_PER_VALUE: usize = 10;
+const HASHES: u64 = 7;
+
+impl BloomFilter {
+    pub fn new(values: usize) -> Self {
+        let words = values.saturating_mul(BITS_PER_VALUE).div_ceil(64).max(1);
+        BloomFilter {
+            bits: vec![0; words],
+        }
+    }
+
+    pub fn insert(&mut self, value: &serde_json::Value) {
+        for bit in self.bit_positions(value) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn might_contain(&self, value: &serde_json::Value) -> bool {
+        self.bit_positions(value)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // the halves of one hash are mixed to make as many as we need. the
+    // filter's size is a power of two, so the step between bits is kept odd
+    // to stop them landing on each other. values are hashed the way the join
+    // compares them, so `1` and `1.0` set the same bits
+    fn bit_positions(&self, value: &serde_json::Value) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        distinct_key(value).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let (first, second) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        let len = self.bits.len() as u64 * 64;
+
+        (0..HASHES).map(move |i| {
+            usize::try_from(first.wrapping_add(i.wrapping_mul(second)) % len).unwrap_or(0)
+        })
+    }
+}
+
+// a bloom filter of the keys on a join's build side, handed down to the
+// scans on its probe side so rows that can't match are dropped as they are
+// read. it lets everything through until the build side has been read
+pub struct RuntimeFilter {
+    column: Column,
+    bloom: OnceCell<BloomFilter>,
+}
+
+impl RuntimeFilter {
+    pub fn new(column: Column) -> Self {
+        RuntimeFilter {
+            column,
+            bloom: OnceCell::new(),
+        }
+    }
+
+    pub fn column(&self) -> &Column {
+        &self.column
+    }
+
+    pub fn set(&self, bloom: BloomFilter) {
+        // each join only builds once
+        let _ = self.bloom.set(bloom);
+    }
+
+    pub fn bloom(&self) -> Option<&BloomFilter> {
+        self.bloom.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BloomFilter;
+    use serde_json::json;
+
+    #[test]
+    fn test_numbers_are_added_by_value() {
+        let mut bloom = BloomFilter::new(10);
+        bloom.insert(&json!(1));
+        bloom.insert(&json!(2.0));
+
+        assert!(bloom.might_contain(&json!(1.0)));
+        assert!(bloom.might_contain(&json!(2)));
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        let mut bloom = BloomFilter::new(1000);
+        for i in 0..1000 {
+            bloom.insert(&json!(i));
+        }
+
+        assert!((0..1000).all(|i| bloom.might_contain(&json!(i))));
+
+        // a few false positives are fine, but not many
+        let false_positives = (1000..11000)
+            .filter(|i| bloom.might_contain(&json!(i)))
+            .count();
+        assert!(false_positives < 500);
+    }
+}
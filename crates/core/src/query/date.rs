@@ -0,0 +1,402 @@
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// there is no date type in JSON, so dates are kept as `2021-01-01` strings
+// and timestamps as `2021-01-01 12:30:00`, which sort the same as text
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Timestamp {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+impl Timestamp {
+    // `2021-01-01`, `2021-01-01 12:30:00` or `2021-01-01T12:30:00`
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (date, time) = match s.split_once([' ', 'T']) {
+            Some((date, time)) => (date, Some(time)),
+            None => (s, None),
+        };
+
+        let [year, month, day] = numbers(date, '-')?;
+        let [hour, minute, second] = match time {
+            Some(time) => numbers(time, ':')?,
+            None => [0, 0, 0],
+        };
+
+        let timestamp = Timestamp {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        };
+
+        // anything out of range, like the 30th of February, won't survive
+        // the trip to days and back
+        let valid = from_days(timestamp.days()) == (year, month, day)
+            && (0..24).contains(&hour)
+            && (0..60).contains(&minute)
+            && (0..60).contains(&second);
+
+        valid.then_some(timestamp)
+    }
+
+    pub fn now() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+
+        Self::from_seconds(i64::try_from(seconds).unwrap_or(i64::MAX))
+    }
+
+    // seconds since 1970-01-01 00:00:00
+    fn from_seconds(seconds: i64) -> Self {
+        let (year, month, day) = from_days(seconds.div_euclid(86_400));
+        let time = seconds.rem_euclid(86_400);
+
+        Timestamp {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time % 3600 / 60,
+            second: time % 60,
+        }
+    }
+
+    fn seconds(&self) -> i64 {
+        self.days() * 86_400 + self.hour * 3600 + self.minute * 60 + self.second
+    }
+
+    // months go first and keep the time of day, landing on the last day of
+    // the month if the day doesn't exist, so `2021-01-31 + 1 month` is
+    // `2021-02-28` like postgres
+    fn add(&self, interval: &Interval) -> Option<Self> {
+        let months = (self.year * 12 + self.month - 1).checked_add(interval.months)?;
+        let (year, month) = (months.div_euclid(12), months.rem_euclid(12) + 1);
+        let last_day = days(year, month + 1, 1) - days(year, month, 1);
+        let moved = Timestamp {
+            year,
+            month,
+            day: self.day.min(last_day),
+            ..*self
+        };
+
+        let seconds = moved
+            .seconds()
+            .checked_add(interval.days.checked_mul(86_400)?)?
+            .checked_add(interval.seconds)?;
+        Some(Self::from_seconds(seconds))
+    }
+
+    pub fn date_string(&self) -> String {
+        self.format("%Y-%m-%d")
+    }
+
+    pub fn timestamp_string(&self) -> String {
+        self.format("%Y-%m-%d %H:%M:%S")
+    }
+
+    // `date_part('year', x)`, days of the week count from sunday as 0
+    pub fn part(&self, field: &str) -> Option<i64> {
+        match field.to_lowercase().as_str() {
+            "year" => Some(self.year),
+            "month" => Some(self.month),
+            "day" => Some(self.day),
+            "hour" => Some(self.hour),
+            "minute" => Some(self.minute),
+            "second" => Some(self.second),
+            "dow" => Some((self.days() + 4).rem_euclid(7)),
+            "doy" => Some(self.days() - days(self.year, 1, 1) + 1),
+            _ => None,
+        }
+    }
+
+    // `strftime('%Y', x)` like sqlite, anything it doesn't know is left as it is
+    pub fn format(&self, pattern: &str) -> String {
+        let mut output = String::new();
+        let mut chars = pattern.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('Y') => write!(output, "{:04}", self.year),
+                Some('m') => write!(output, "{:02}", self.month),
+                Some('d') => write!(output, "{:02}", self.day),
+                Some('H') => write!(output, "{:02}", self.hour),
+                Some('M') => write!(output, "{:02}", self.minute),
+                Some('S') => write!(output, "{:02}", self.second),
+                Some('j') => write!(output, "{:03}", self.part("doy").unwrap_or(0)),
+                Some('w') => write!(output, "{}", self.part("dow").unwrap_or(0)),
+                Some('%') | None => write!(output, "%"),
+                Some(other) => write!(output, "%{other}"),
+            }
+            // writing to a string can't fail
+            .unwrap_or_default();
+        }
+
+        output
+    }
+
+    fn days(&self) -> i64 {
+        days(self.year, self.month, self.day)
+    }
+}
+
+// `interval '7 days'`, kept as text like dates are. months and days are kept
+// apart from seconds because neither is always the same length
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Interval {
+    months: i64,
+    days: i64,
+    seconds: i64,
+}
+
+impl Interval {
+    // `1 year 2 months`, `-3 days`, `1 day 02:00:00` or `90 minutes`
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut interval = Interval::default();
+        let mut words = s.split_whitespace().peekable();
+        words.peek()?;
+
+        while let Some(word) = words.next() {
+            if word.contains(':') {
+                interval.seconds = interval.seconds.checked_add(time(word)?)?;
+                continue;
+            }
+
+            let amount: i64 = word.parse().ok()?;
+            let unit = words.next()?.to_lowercase();
+            let (months, days, seconds) = match unit.strip_suffix('s').unwrap_or(&unit) {
+                "year" => (12, 0, 0),
+                "mon" | "month" => (1, 0, 0),
+                "week" => (0, 7, 0),
+                "day" => (0, 1, 0),
+                "hour" => (0, 0, 3600),
+                "min" | "minute" => (0, 0, 60),
+                "sec" | "second" => (0, 0, 1),
+                _ => return None,
+            };
+            interval.months = interval.months.checked_add(amount.checked_mul(months)?)?;
+            interval.days = interval.days.checked_add(amount.checked_mul(days)?)?;
+            interval.seconds = interval.seconds.checked_add(amount.checked_mul(seconds)?)?;
+        }
+
+        Some(interval)
+    }
+
+    // how postgres writes them, `1 year 2 mons 3 days 04:05:06`
+    pub fn interval_string(&self) -> String {
+        let plural = |n: i64, unit: &str| {
+            if n == 1 {
+                format!("{n} {unit}")
+            } else {
+                format!("{n} {unit}s")
+            }
+        };
+
+        let mut parts = vec![];
+        let (years, months) = (self.months / 12, self.months % 12);
+        if years != 0 {
+            parts.push(plural(years, "year"));
+        }
+        if months != 0 {
+            parts.push(plural(months, "mon"));
+        }
+        if self.days != 0 {
+            parts.push(plural(self.days, "day"));
+        }
+        if self.seconds != 0 || parts.is_empty() {
+            let sign = if self.seconds < 0 { "-" } else { "" };
+            let seconds = self.seconds.unsigned_abs();
+            parts.push(format!(
+                "{sign}{:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds % 3600 / 60,
+                seconds % 60
+            ));
+        }
+
+        parts.join(" ")
+    }
+
+    fn negate(&self) -> Option<Self> {
+        Some(Interval {
+            months: self.months.checked_neg()?,
+            days: self.days.checked_neg()?,
+            seconds: self.seconds.checked_neg()?,
+        })
+    }
+}
+
+// `+` and `-` on dates, which are only text, so `None` unless the text is
+// the right kind. a date plus whole days stays a date so it still compares
+// equal to other dates, two dates apart are a number of days and two
+// timestamps apart are an interval of days and time, all as in postgres
+pub fn arithmetic(left: &str, right: &str, subtract: bool) -> Option<serde_json::Value> {
+    let is_date = |s: &str| !s.trim().contains([' ', 'T']);
+
+    if let (Some(timestamp), Some(interval)) = (Timestamp::parse(left), Interval::parse(right)) {
+        let interval = if subtract {
+            interval.negate()?
+        } else {
+            interval
+        };
+        let result = timestamp.add(&interval)?;
+
+        return Some(if is_date(left) && interval.seconds == 0 {
+            result.date_string().into()
+        } else {
+            result.timestamp_string().into()
+        });
+    }
+
+    if let (Some(interval), Some(_), false) =
+        (Interval::parse(left), Timestamp::parse(right), subtract)
+    {
+        return arithmetic(right, &interval.interval_string(), false);
+    }
+
+    if let (Some(left_timestamp), Some(right_timestamp), true) =
+        (Timestamp::parse(left), Timestamp::parse(right), subtract)
+    {
+        if is_date(left) && is_date(right) {
+            return Some((left_timestamp.days() - right_timestamp.days()).into());
+        }
+
+        let seconds = left_timestamp.seconds() - right_timestamp.seconds();
+        let interval = Interval {
+            months: 0,
+            days: seconds / 86_400,
+            seconds: seconds % 86_400,
+        };
+        return Some(interval.interval_string().into());
+    }
+
+    None
+}
+
+// `04:05:06`, `-02:00` or `90:00`
+fn time(s: &str) -> Option<i64> {
+    let (sign, s) = match s.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, s),
+    };
+
+    let mut parts = s.split(':').map(|part| {
+        part.chars()
+            .all(|c| c.is_ascii_digit())
+            .then(|| part.parse::<i64>().ok())
+            .flatten()
+    });
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next().unwrap_or(Some(0))?;
+
+    let valid = parts.next().is_none() && minutes < 60 && seconds < 60;
+    let total = hours.checked_mul(3600)? + minutes * 60 + seconds;
+    valid.then_some(sign * total)
+}
+
+fn numbers(s: &str, separator: char) -> Option<[i64; 3]> {
+    let mut parts = s.split(separator).map(|part| {
+        part.chars()
+            .all(|c| c.is_ascii_digit())
+            .then(|| part.parse().ok())
+            .flatten()
+    });
+
+    let numbers = [parts.next()??, parts.next()??, parts.next()??];
+
+    parts.next().is_none().then_some(numbers)
+}
+
+// days since 1970-01-01, from http://howardhinnant.github.io/date_algorithms.html
+fn days(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arithmetic, Interval, Timestamp};
+
+    #[test]
+    fn test_parse_and_format() {
+        let timestamp = Timestamp::parse("2024-02-29T13:05:09").unwrap();
+
+        assert_eq!(timestamp.date_string(), "2024-02-29");
+        assert_eq!(timestamp.timestamp_string(), "2024-02-29 13:05:09");
+        assert_eq!(
+            timestamp.format("%d/%m/%Y day %j, 100%%"),
+            "29/02/2024 day 060, 100%"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_impossible_dates() {
+        assert_eq!(Timestamp::parse("2023-02-29"), None);
+        assert_eq!(Timestamp::parse("2023-13-01"), None);
+        assert_eq!(Timestamp::parse("2023-01-01 24:00:00"), None);
+        assert_eq!(Timestamp::parse("01/01/2023"), None);
+    }
+
+    #[test]
+    fn test_day_of_week() {
+        // a monday
+        let timestamp = Timestamp::parse("2021-01-04").unwrap();
+
+        assert_eq!(timestamp.part("dow"), Some(1));
+    }
+
+    #[test]
+    fn test_interval_arithmetic() {
+        assert_eq!(
+            arithmetic("2024-01-31", "1 month", false),
+            Some("2024-02-29".into())
+        );
+        assert_eq!(
+            arithmetic("2021-01-01 00:30:00", "1 hour", true),
+            Some("2020-12-31 23:30:00".into())
+        );
+        assert_eq!(arithmetic("2021-01-01", "some text", false), None);
+        assert_eq!(
+            Interval::parse("36 hours").map(|interval| interval.interval_string()),
+            Some("36:00:00".into())
+        );
+    }
+}
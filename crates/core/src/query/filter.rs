@@ -1,22 +1,230 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use super::bloom::RuntimeFilter;
+use super::date::{self, Interval, Timestamp};
+use super::operator::{BoxOperator, Context, Operator};
+use super::order_by::compare_values;
+use super::parallel;
+use super::scalar_function::scalar_function;
 use super::QueryError;
 use crate::types::AggregateFunctionName;
+use crate::types::Cost;
+use crate::types::DataType;
 use crate::types::FunctionName;
 use crate::types::Row;
 use crate::types::Schema;
-use crate::types::{Expr, Op};
+use crate::types::{Expr, Op, Quantifier};
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum FilterError {
-    ExpectedInt { value: serde_json::Value },
-    ExpectedBooleanType { value: serde_json::Value },
+    ExpectedInt {
+        value: serde_json::Value,
+    },
+    ExpectedNumber {
+        value: serde_json::Value,
+    },
+    ExpectedBooleanType {
+        value: serde_json::Value,
+    },
+    ExpectedArray {
+        value: serde_json::Value,
+    },
+    ExpectedString {
+        value: serde_json::Value,
+    },
+    LikePatternEndsWithEscape {
+        pattern: String,
+    },
+    CannotCast {
+        value: serde_json::Value,
+        data_type: DataType,
+    },
+}
+
+// what a filter keeps rows by
+pub enum Predicate<'a> {
+    // a predicate that doesn't mention any columns, like `1 = 1`, is the
+    // same for every row
+    Constant(bool),
+    Expr(&'a Expr),
+    // subqueries, which need more than the row to decide
+    Row(RowPredicate<'a>),
+}
+
+type RowPredicate<'a> = Box<dyn FnMut(&Row, &Schema) -> Result<bool, QueryError> + 'a>;
+
+// rows are filtered in batches so that big inputs can still be split across
+// threads. batches start small, so a filter under a `limit` doesn't read
+// much more than it needs, and grow as more rows are asked for
+const FIRST_BATCH_SIZE: usize = 64;
+const MAX_BATCH_SIZE: usize = 64 * 1024;
+
+pub struct FilterRows<'a> {
+    input: BoxOperator<'a>,
+    predicate: Predicate<'a>,
+    batch: std::vec::IntoIter<Row>,
+    batch_size: usize,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> FilterRows<'a> {
+    pub fn new(
+        input: BoxOperator<'a>,
+        predicate: Predicate<'a>,
+        context: &Rc<Context<'a>>,
+    ) -> Self {
+        FilterRows {
+            input,
+            predicate,
+            batch: vec![].into_iter(),
+            batch_size: FIRST_BATCH_SIZE,
+            context: context.clone(),
+        }
+    }
+
+    fn next_batch(&mut self, where_expr: &Expr) -> Result<bool, QueryError> {
+        let rows = self.input.next_batch(self.batch_size)?;
+        if rows.is_empty() {
+            return Ok(false);
+        }
+
+        let schema = self.input.schema();
+        let threads = self.context.threads();
+        let rows = self
+            .context
+            .with_cost(|cost| filter_rows(rows, schema, where_expr, cost, threads))?;
+
+        self.batch = rows.into_iter();
+        self.batch_size = (self.batch_size * 2).min(MAX_BATCH_SIZE);
+        Ok(true)
+    }
+}
+
+impl Iterator for FilterRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.predicate {
+            Predicate::Constant(true) => self.input.next(),
+            Predicate::Constant(false) => None,
+            Predicate::Expr(where_expr) => {
+                let where_expr = *where_expr;
+                loop {
+                    if let Some(row) = self.batch.next() {
+                        return Some(Ok(row));
+                    }
+
+                    match self.next_batch(where_expr) {
+                        Ok(true) => {}
+                        Ok(false) => return None,
+                        Err(error) => return Some(Err(error)),
+                    }
+                }
+            }
+            Predicate::Row(keeps) => loop {
+                let row = match self.input.next()? {
+                    Ok(row) => row,
+                    Err(error) => return Some(Err(error)),
+                };
+
+                self.context.increment_rows_processed();
+                match keeps(&row, self.input.schema()) {
+                    Ok(true) => return Some(Ok(row)),
+                    Ok(false) => {}
+                    Err(error) => return Some(Err(error)),
+                }
+            },
+        }
+    }
+}
+
+impl Operator for FilterRows<'_> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+
+    // rows that can't match are dropped either way, so they may as well be
+    // dropped before they're filtered
+    fn push_runtime_filter(&mut self, filter: &Rc<RuntimeFilter>) {
+        self.input.push_runtime_filter(filter);
+    }
+}
+
+pub fn filter_rows(
+    rows: Vec<Row>,
+    schema: &Schema,
+    where_expr: &Expr,
+    cost: &mut Cost,
+    threads: usize,
+) -> Result<Vec<Row>, QueryError> {
+    let partitions = parallel::partition_count(rows.len(), threads);
+
+    filter_partitioned(rows, schema, where_expr, cost, partitions)
+}
+
+// each partition is filtered by its own worker and the results are put back
+// together in their original order, so the output is the same however many
+// partitions there are
+pub fn filter_partitioned(
+    rows: Vec<Row>,
+    schema: &Schema,
+    where_expr: &Expr,
+    cost: &mut Cost,
+    partitions: usize,
+) -> Result<Vec<Row>, QueryError> {
+    let results = parallel::map_partitions(parallel::split(rows, partitions), |partition| {
+        let mut cost = Cost::new();
+        filter_partition(partition, schema, where_expr, &mut cost).map(|rows| (rows, cost))
+    });
+
+    let mut filtered_rows = vec![];
+    for result in results {
+        let (rows, partition_cost) = result?;
+        cost.extend(&partition_cost);
+        filtered_rows.extend(rows);
+    }
+    Ok(filtered_rows)
+}
+
+fn filter_partition(
+    rows: Vec<Row>,
+    schema: &Schema,
+    where_expr: &Expr,
+    cost: &mut Cost,
+) -> Result<Vec<Row>, QueryError> {
+    let mut filtered_rows = vec![];
+
+    for row in rows {
+        cost.increment_rows_processed();
+        if apply_predicate(&row, schema, where_expr)? {
+            filtered_rows.push(row);
+        }
+    }
+    Ok(filtered_rows)
 }
 
 pub fn apply_predicate(row: &Row, schema: &Schema, where_expr: &Expr) -> Result<bool, QueryError> {
-    match evaluate_expr(row, schema, where_expr)? {
-        serde_json::Value::Bool(b) => Ok(b),
-        other => Err(QueryError::FilterError(FilterError::ExpectedBooleanType {
-            value: other,
-        })),
+    is_true(&evaluate_expr(row, schema, where_expr)?)
+}
+
+// whether a predicate keeps a row. a null predicate is unknown rather than
+// true, so the row is dropped
+pub fn is_true(value: &serde_json::Value) -> Result<bool, QueryError> {
+    Ok(as_bool(value).map_err(QueryError::FilterError)? == Some(true))
+}
+
+// only booleans can be used as predicates, there is no truthiness for other
+// values like `where 1` or `where name`. null is unknown
+fn as_bool(value: &serde_json::Value) -> Result<Option<bool>, FilterError> {
+    match value {
+        serde_json::Value::Bool(b) => Ok(Some(*b)),
+        serde_json::Value::Null => Ok(None),
+        other => Err(FilterError::ExpectedBooleanType {
+            value: other.clone(),
+        }),
     }
 }
 
@@ -39,13 +247,97 @@ pub fn evaluate_expr(
             })
             .cloned(),
         Expr::Literal { literal } => Ok(literal.clone()),
-        Expr::Nested { expr } => evaluate_expr(row, schema, expr),
-        Expr::FunctionCall { .. } => todo!("function call in evaluate_expr"),
+        Expr::Nested { expr } | Expr::Alias { expr, .. } => evaluate_expr(row, schema, expr),
+        Expr::Not { expr } => not(&evaluate_expr(row, schema, expr)?),
+        Expr::FunctionCall {
+            function_name: FunctionName::Scalar(scalar_function_name),
+            args,
+            ..
+        } => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate_expr(row, schema, arg))
+                .collect::<Result<_, _>>()?;
+
+            scalar_function(scalar_function_name).call(args)
+        }
+        // aggregates are worked out over all rows by `evaluate_aggregate_expr`,
+        // so the only way to get here is an aggregate inside an aggregate
+        Expr::FunctionCall {
+            function_name: FunctionName::Aggregate(_),
+            ..
+        } => Err(QueryError::CannotNestAggregateFunctions),
+        // these are run by the filter, not by evaluating row by row
+        Expr::InSubquery { .. } | Expr::Exists { .. } | Expr::QuantifiedSubquery { .. } => {
+            Err(QueryError::SubqueryNotSupportedHere)
+        }
+        // these are expanded into columns before projecting
+        Expr::Wildcard { .. } => Err(QueryError::WildcardNotSupportedHere),
+        Expr::Parameter { index } => Err(QueryError::ParameterNotBound { index: *index }),
+        Expr::Array { items } => Ok(serde_json::Value::Array(
+            items
+                .iter()
+                .map(|item| evaluate_expr(row, schema, item))
+                .collect::<Result<_, _>>()?,
+        )),
+        Expr::Index { expr, index } => {
+            let value = evaluate_expr(row, schema, expr)?;
+            let index = evaluate_expr(row, schema, index)?;
+
+            array_index(&value, &index).map_err(QueryError::FilterError)
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let value = evaluate_expr(row, schema, expr)?;
+            let list = list
+                .iter()
+                .map(|item| evaluate_expr(row, schema, item))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(in_list(&value, &list, *negated))
+        }
+        Expr::Cast { expr, data_type } => {
+            let value = evaluate_expr(row, schema, expr)?;
+
+            cast(value, data_type).map_err(QueryError::FilterError)
+        }
+        Expr::IsNull { expr, negated } => {
+            let value = evaluate_expr(row, schema, expr)?;
+
+            Ok(serde_json::Value::Bool(value.is_null() != *negated))
+        }
+        Expr::Like {
+            expr,
+            pattern,
+            negated,
+            case_insensitive,
+            escape,
+        } => {
+            let value = evaluate_expr(row, schema, expr)?;
+            let pattern = evaluate_expr(row, schema, pattern)?;
+
+            like(&value, &pattern, *negated, *case_insensitive, *escape)
+                .map_err(QueryError::FilterError)
+        }
+        Expr::Quantified {
+            left,
+            op,
+            quantifier,
+            right,
+        } => {
+            let left = evaluate_expr(row, schema, left)?;
+            let right = evaluate_expr(row, schema, right)?;
+
+            quantified(&left, op, quantifier, &right).map_err(QueryError::FilterError)
+        }
     }
 }
 
 pub fn evaluate_aggregate_expr(
-    all_rows: &Vec<Row>,
+    all_rows: &[Row],
     schema: &Schema,
     expr: &Expr,
 ) -> Result<serde_json::Value, QueryError> {
@@ -56,87 +348,798 @@ pub fn evaluate_aggregate_expr(
 
             match_op(&left, op, &right).map_err(QueryError::FilterError)
         }
-        Expr::Column { .. } => panic!("column in evaluate_aggregate_expr"),
+        // only grouped columns get this far, and they are the same for every
+        // row in the group, so the first one will do
+        Expr::Column { .. } => match all_rows.first() {
+            Some(row) => evaluate_expr(row, schema, expr),
+            None => Ok(serde_json::Value::Null),
+        },
         Expr::Literal { literal } => Ok(literal.clone()),
-        Expr::Nested { expr } => evaluate_aggregate_expr(all_rows, schema, expr),
+        Expr::Nested { expr } | Expr::Alias { expr, .. } => {
+            evaluate_aggregate_expr(all_rows, schema, expr)
+        }
+        Expr::InSubquery { .. } | Expr::Exists { .. } | Expr::QuantifiedSubquery { .. } => {
+            Err(QueryError::SubqueryNotSupportedHere)
+        }
+        Expr::Wildcard { .. } => Err(QueryError::WildcardNotSupportedHere),
+        Expr::Parameter { index } => Err(QueryError::ParameterNotBound { index: *index }),
+        Expr::Not { expr } => not(&evaluate_aggregate_expr(all_rows, schema, expr)?),
+        Expr::Array { items } => Ok(serde_json::Value::Array(
+            items
+                .iter()
+                .map(|item| evaluate_aggregate_expr(all_rows, schema, item))
+                .collect::<Result<_, _>>()?,
+        )),
+        Expr::Index { expr, index } => {
+            let value = evaluate_aggregate_expr(all_rows, schema, expr)?;
+            let index = evaluate_aggregate_expr(all_rows, schema, index)?;
+
+            array_index(&value, &index).map_err(QueryError::FilterError)
+        }
+        Expr::InList {
+            expr,
+            list,
+            negated,
+        } => {
+            let value = evaluate_aggregate_expr(all_rows, schema, expr)?;
+            let list = list
+                .iter()
+                .map(|item| evaluate_aggregate_expr(all_rows, schema, item))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(in_list(&value, &list, *negated))
+        }
+        Expr::Cast { expr, data_type } => {
+            let value = evaluate_aggregate_expr(all_rows, schema, expr)?;
+
+            cast(value, data_type).map_err(QueryError::FilterError)
+        }
+        Expr::IsNull { expr, negated } => {
+            let value = evaluate_aggregate_expr(all_rows, schema, expr)?;
+
+            Ok(serde_json::Value::Bool(value.is_null() != *negated))
+        }
+        Expr::Like {
+            expr,
+            pattern,
+            negated,
+            case_insensitive,
+            escape,
+        } => {
+            let value = evaluate_aggregate_expr(all_rows, schema, expr)?;
+            let pattern = evaluate_aggregate_expr(all_rows, schema, pattern)?;
+
+            like(&value, &pattern, *negated, *case_insensitive, *escape)
+                .map_err(QueryError::FilterError)
+        }
+        Expr::Quantified {
+            left,
+            op,
+            quantifier,
+            right,
+        } => {
+            let left = evaluate_aggregate_expr(all_rows, schema, left)?;
+            let right = evaluate_aggregate_expr(all_rows, schema, right)?;
+
+            quantified(&left, op, quantifier, &right).map_err(QueryError::FilterError)
+        }
         Expr::FunctionCall {
             function_name,
             args,
-        } => evaluate_function_call(function_name, args, all_rows, schema),
+            distinct,
+        } => evaluate_function_call(function_name, args, *distinct, all_rows, schema),
     }
 }
 
 fn evaluate_function_call(
     function_name: &FunctionName,
     args: &[Expr],
+    distinct: bool,
     all_rows: &[Row],
     schema: &Schema,
 ) -> Result<serde_json::Value, QueryError> {
     match function_name {
-        FunctionName::Aggregate(agg) => match agg {
-            AggregateFunctionName::Sum => {
-                let expr = args.first().ok_or(QueryError::ArgumentNotFound)?;
+        // the arguments may contain aggregates, `upper(min(name))`
+        FunctionName::Scalar(scalar_function_name) => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate_aggregate_expr(all_rows, schema, arg))
+                .collect::<Result<_, _>>()?;
 
-                let sum = all_rows.iter().try_fold(0, |total, all_row| {
-                    let value = evaluate_expr(all_row, schema, expr)?;
+            scalar_function(scalar_function_name).call(args)
+        }
+        FunctionName::Aggregate(agg) => {
+            let expr = args.first().ok_or(QueryError::ArgumentNotFound)?;
 
-                    if let serde_json::Value::Number(a) = value {
-                        if let Some(a) = a.as_i64() {
-                            return Ok(total + a);
-                        }
-                    };
+            // nulls are skipped by every aggregate apart from `array_agg`, and
+            // with `distinct` so is anything we've already seen
+            let keep_nulls = matches!(agg, AggregateFunctionName::ArrayAgg);
+            let mut seen = HashSet::new();
+            let mut values = vec![];
 
-                    Err(QueryError::TypeMismatch {
-                        expected: "i64".into(),
-                    })
-                })?;
+            for row in all_rows {
+                let value = evaluate_expr(row, schema, expr)?;
 
-                Ok(sum.into())
+                if (value.is_null() && !keep_nulls)
+                    || (distinct && !seen.insert(distinct_key(&value)))
+                {
+                    continue;
+                }
+
+                values.push(value);
             }
-        },
+
+            match agg {
+                AggregateFunctionName::Sum => sum(&values),
+                AggregateFunctionName::Count => Ok(values.len().into()),
+                AggregateFunctionName::Avg => Ok(avg(&as_floats(&values)?).into()),
+                AggregateFunctionName::Min => extreme(values, Ordering::Less),
+                AggregateFunctionName::Max => extreme(values, Ordering::Greater),
+                AggregateFunctionName::ArrayAgg => Ok(if values.is_empty() {
+                    serde_json::Value::Null
+                } else {
+                    serde_json::Value::Array(values)
+                }),
+                AggregateFunctionName::StringAgg => {
+                    let separator = args.get(1).ok_or(QueryError::ArgumentNotFound)?;
+                    let separator = evaluate_aggregate_expr(all_rows, schema, separator)?;
+
+                    string_agg(&values, &separator)
+                }
+                AggregateFunctionName::Variance => Ok(variance(&as_floats(&values)?).into()),
+                AggregateFunctionName::Stddev => {
+                    Ok(variance(&as_floats(&values)?).map(f64::sqrt).into())
+                }
+                AggregateFunctionName::PercentileCont => {
+                    let fraction = args.get(1).ok_or(QueryError::ArgumentNotFound)?;
+                    let fraction = evaluate_aggregate_expr(all_rows, schema, fraction)?;
+                    let fraction = as_floats(&[fraction])?[0];
+
+                    Ok(percentile_cont(as_floats(&values)?, fraction)?.into())
+                }
+            }
+        }
+    }
+}
+
+// the smallest or largest value with the same ordering as `order by`, null
+// when there are none. only numbers, strings or booleans can be compared, and
+// they all have to be the same kind
+fn extreme(
+    values: Vec<serde_json::Value>,
+    wanted: Ordering,
+) -> Result<serde_json::Value, QueryError> {
+    let comparable = |value: &serde_json::Value| {
+        matches!(
+            value,
+            serde_json::Value::Number(_)
+                | serde_json::Value::String(_)
+                | serde_json::Value::Bool(_)
+        )
+    };
+
+    let mut values = values.into_iter();
+    let Some(mut best) = values.next() else {
+        return Ok(serde_json::Value::Null);
+    };
+
+    for value in values {
+        if !comparable(&value) || std::mem::discriminant(&value) != std::mem::discriminant(&best) {
+            return Err(QueryError::TypeMismatch {
+                expected: "values of one comparable type".into(),
+            });
+        }
+        if compare_values(&value, &best) == wanted {
+            best = value;
+        }
+    }
+
+    if comparable(&best) {
+        Ok(best)
+    } else {
+        Err(QueryError::TypeMismatch {
+            expected: "values of one comparable type".into(),
+        })
     }
 }
 
+// `string_agg(name, ', ')`, null when there is nothing to join
+fn string_agg(
+    values: &[serde_json::Value],
+    separator: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let as_str = |value: &serde_json::Value| {
+        value
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| QueryError::TypeMismatch {
+                expected: "string".into(),
+            })
+    };
+
+    if values.is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let separator = as_str(separator)?;
+    let strings = values.iter().map(as_str).collect::<Result<Vec<_>, _>>()?;
+
+    Ok(strings.join(&separator).into())
+}
+
+// the mean, as a float even when every value is an integer. null when there
+// is nothing to average
+fn avg(values: &[f64]) -> Option<f64> {
+    let mut count = 0.0;
+    let mut total = 0.0;
+
+    for value in values {
+        count += 1.0;
+        total += value;
+    }
+
+    (!values.is_empty()).then(|| total / count)
+}
+
+// sample variance using Welford's algorithm, which avoids the cancellation
+// you get from subtracting two big sums of squares. null with fewer than two
+// values
+fn variance(values: &[f64]) -> Option<f64> {
+    let mut count = 0.0;
+    let mut mean = 0.0;
+    let mut sum_of_squares = 0.0;
+
+    for value in values {
+        count += 1.0;
+        let delta = value - mean;
+        mean += delta / count;
+        sum_of_squares += delta * (value - mean);
+    }
+
+    (values.len() > 1).then(|| sum_of_squares / (count - 1.0))
+}
+
+// the value at `fraction` of the way through the sorted values, interpolating
+// between the two nearest when it falls in between
+fn percentile_cont(mut values: Vec<f64>, fraction: f64) -> Result<Option<f64>, QueryError> {
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(QueryError::TypeMismatch {
+            expected: "fraction between 0 and 1".into(),
+        });
+    }
+
+    values.sort_by(f64::total_cmp);
+
+    let Some(last) = values.len().checked_sub(1) else {
+        return Ok(None);
+    };
+
+    #[allow(clippy::cast_precision_loss)]
+    let position = fraction * last as f64;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+
+    #[allow(clippy::cast_precision_loss)]
+    let weight = position - lower as f64;
+
+    Ok(Some(
+        values[lower] + (values[upper] - values[lower]) * weight,
+    ))
+}
+
+fn as_floats(values: &[serde_json::Value]) -> Result<Vec<f64>, QueryError> {
+    values
+        .iter()
+        .map(serde_json::Value::as_f64)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| QueryError::TypeMismatch {
+            expected: "number".into(),
+        })
+}
+
+// what `distinct` tells values apart by. floats with nothing after the
+// point are written as integers, so `1` and `1.0` are the same value, as they
+// are for `=`
+pub fn distinct_key(value: &serde_json::Value) -> String {
+    fn normalise(value: &serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Number(number) if number.is_f64() => number
+                .as_f64()
+                .filter(|float| float.fract() == 0.0)
+                .and_then(float_to_int)
+                .unwrap_or_else(|| value.clone()),
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(normalise).collect())
+            }
+            value => value.clone(),
+        }
+    }
+
+    normalise(value).to_string()
+}
+
+// integers stay integers, anything with a float in it becomes a float. null
+// when there is nothing to add up, like postgres, rather than 0
+fn sum(values: &[serde_json::Value]) -> Result<serde_json::Value, QueryError> {
+    if values.is_empty() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    if let Some(ints) = values
+        .iter()
+        .map(serde_json::Value::as_i64)
+        .collect::<Option<Vec<_>>>()
+    {
+        return Ok(ints.into_iter().sum::<i64>().into());
+    }
+
+    let floats = as_floats(values)?;
+
+    Ok(floats.into_iter().sum::<f64>().into())
+}
+
 fn match_op(
     value: &serde_json::Value,
     op: &Op,
     literal: &serde_json::Value,
 ) -> Result<serde_json::Value, FilterError> {
     match op {
-        Op::Equals => Ok(serde_json::Value::Bool(value == literal)),
-        Op::GreaterThan => {
-            let left = as_int(value)?;
-            let right = as_int(literal)?;
-            Ok(serde_json::Value::Bool(left > right))
-        }
-        Op::GreaterThanOrEqual => {
-            let left = as_int(value)?;
-            let right = as_int(literal)?;
-            Ok(serde_json::Value::Bool(left >= right))
-        }
-        Op::LessThan => {
-            let left = as_int(value)?;
-            let right = as_int(literal)?;
-            Ok(serde_json::Value::Bool(left < right))
-        }
-        Op::LessThanOrEqual => {
-            let left = as_int(value)?;
-            let right = as_int(literal)?;
-            Ok(serde_json::Value::Bool(left <= right))
-        }
-        Op::Add => {
-            let left = as_int(value)?;
-            let right = as_int(literal)?;
-            Ok(serde_json::Value::Number((left + right).into()))
-        }
-        Op::Subtract => {
-            let left = as_int(value)?;
-            let right = as_int(literal)?;
-            Ok(serde_json::Value::Number((left - right).into()))
+        Op::Equals => Ok(unknown_if_none(equals(value, literal))),
+        Op::NotEquals => Ok(unknown_if_none(equals(value, literal).map(|b| !b))),
+        Op::GreaterThan => Ok(unknown_if_none(
+            compare(value, literal)?.map(std::cmp::Ordering::is_gt),
+        )),
+        Op::GreaterThanOrEqual => Ok(unknown_if_none(
+            compare(value, literal)?.map(std::cmp::Ordering::is_ge),
+        )),
+        Op::LessThan => Ok(unknown_if_none(
+            compare(value, literal)?.map(std::cmp::Ordering::is_lt),
+        )),
+        Op::LessThanOrEqual => Ok(unknown_if_none(
+            compare(value, literal)?.map(std::cmp::Ordering::is_le),
+        )),
+        Op::Add => date_arithmetic(value, literal, false).map_or_else(
+            || arithmetic(value, literal, |a, b| a + b, |a, b| a + b),
+            Ok,
+        ),
+        Op::Subtract => date_arithmetic(value, literal, true).map_or_else(
+            || arithmetic(value, literal, |a, b| a - b, |a, b| a - b),
+            Ok,
+        ),
+        Op::Multiply => arithmetic(value, literal, |a, b| a * b, |a, b| a * b),
+        Op::JsonGet => Ok(json_get(value, literal)),
+        Op::JsonGetText => Ok(match json_get(value, literal) {
+            serde_json::Value::Null => serde_json::Value::Null,
+            serde_json::Value::String(s) => serde_json::Value::String(s),
+            other => serde_json::Value::String(other.to_string()),
+        }),
+        Op::Contains => contains(value, literal),
+        Op::ContainedBy => contains(literal, value),
+        Op::And => logical(value, literal, false),
+        Op::Or => logical(value, literal, true),
+        Op::Concat => concat(value, literal),
+    }
+}
+
+// `'Track ' || TrackId`, numbers and booleans are written out as text, and
+// null on either side gives null
+fn concat(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+) -> Result<serde_json::Value, FilterError> {
+    let as_text = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => Ok(Some(s.clone())),
+        serde_json::Value::Number(_) | serde_json::Value::Bool(_) => Ok(Some(value.to_string())),
+        serde_json::Value::Null => Ok(None),
+        other => Err(FilterError::ExpectedString {
+            value: other.clone(),
+        }),
+    };
+
+    Ok(match (as_text(left)?, as_text(right)?) {
+        (Some(left), Some(right)) => (left + &right).into(),
+        _ => serde_json::Value::Null,
+    })
+}
+
+// `and` is decided by any false side and `or` by any true side, otherwise a
+// null on either side makes the result unknown
+fn logical(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    decided_by: bool,
+) -> Result<serde_json::Value, FilterError> {
+    match (as_bool(left)?, as_bool(right)?) {
+        (Some(left), _) if left == decided_by => Ok(serde_json::Value::Bool(decided_by)),
+        (_, Some(right)) if right == decided_by => Ok(serde_json::Value::Bool(decided_by)),
+        (Some(_), Some(_)) => Ok(serde_json::Value::Bool(!decided_by)),
+        _ => Ok(serde_json::Value::Null),
+    }
+}
+
+// comparisons with null are unknown rather than true or false, and a where
+// clause treats unknown like false
+fn unknown_if_none(result: Option<bool>) -> serde_json::Value {
+    result.map_or(serde_json::Value::Null, serde_json::Value::Bool)
+}
+
+// `1 = 1.0`, numbers are equal by value whether or not they are floats. null
+// equals nothing, not even null, so `None` means unknown. rows are unequal if
+// any item is, and otherwise unknown if any item is null
+fn equals(left: &serde_json::Value, right: &serde_json::Value) -> Option<bool> {
+    match (left, right) {
+        (serde_json::Value::Null, _) | (_, serde_json::Value::Null) => None,
+        (serde_json::Value::Array(left), serde_json::Value::Array(right)) => {
+            if left.len() != right.len() {
+                return Some(false);
+            }
+
+            let results: Vec<_> = left
+                .iter()
+                .zip(right)
+                .map(|(left, right)| equals(left, right))
+                .collect();
+
+            if results.contains(&Some(false)) {
+                Some(false)
+            } else if results.contains(&None) {
+                None
+            } else {
+                Some(true)
+            }
+        }
+        (serde_json::Value::Number(left), serde_json::Value::Number(right))
+            if left.is_f64() || right.is_f64() =>
+        {
+            Some(left.as_f64() == right.as_f64())
         }
+        _ => Some(left == right),
     }
 }
 
+// integers stay integers, but if either side is a float so is the result
+fn arithmetic(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    ints: fn(i64, i64) -> i64,
+    floats: fn(f64, f64) -> f64,
+) -> Result<serde_json::Value, FilterError> {
+    if left.is_null() || right.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    if let (Some(left), Some(right)) = (left.as_i64(), right.as_i64()) {
+        return Ok(ints(left, right).into());
+    }
+
+    Ok(floats(as_float(left)?, as_float(right)?).into())
+}
+
+// `date '2021-01-01' + interval '7 days'`, anything else is left to
+// `arithmetic`
+fn date_arithmetic(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+    subtract: bool,
+) -> Option<serde_json::Value> {
+    match (left, right) {
+        (serde_json::Value::String(left), serde_json::Value::String(right)) => {
+            date::arithmetic(left, right, subtract)
+        }
+        _ => None,
+    }
+}
+
+// numbers compare as numbers, text (including dates) compares as text, and
+// rows or arrays compare item by item so `(ArtistId, AlbumId) > (82, 264)`
+// works for keyset pagination. `None` means a null got in the way, so the
+// comparison is unknown
+pub fn compare(
+    left: &serde_json::Value,
+    right: &serde_json::Value,
+) -> Result<Option<std::cmp::Ordering>, FilterError> {
+    if left.is_null() || right.is_null() {
+        return Ok(None);
+    }
+
+    if let (serde_json::Value::Array(left), serde_json::Value::Array(right)) = (left, right) {
+        for (left, right) in left.iter().zip(right) {
+            match compare(left, right)? {
+                Some(ordering) if ordering.is_eq() => {}
+                other => return Ok(other),
+            }
+        }
+
+        return Ok(Some(left.len().cmp(&right.len())));
+    }
+
+    if let (Some(left), Some(right)) = (left.as_i64(), right.as_i64()) {
+        return Ok(Some(left.cmp(&right)));
+    }
+
+    if let (serde_json::Value::String(left), serde_json::Value::String(right)) = (left, right) {
+        return Ok(Some(left.cmp(right)));
+    }
+
+    Ok(Some(as_float(left)?.total_cmp(&as_float(right)?)))
+}
+
+// nulls stay null. floats are rounded to integers, and text is parsed as
+// whatever it is being cast to
+fn cast(value: serde_json::Value, data_type: &DataType) -> Result<serde_json::Value, FilterError> {
+    let cast_value = match (data_type, &value) {
+        (_, serde_json::Value::Null)
+        | (DataType::Text, serde_json::Value::String(_))
+        | (DataType::Boolean, serde_json::Value::Bool(_)) => Some(value.clone()),
+        (DataType::Integer, serde_json::Value::Number(number)) => number
+            .as_i64()
+            .map(Into::into)
+            .or_else(|| number.as_f64().and_then(float_to_int)),
+        (DataType::Integer, serde_json::Value::String(s)) => {
+            s.trim().parse::<i64>().ok().map(Into::into)
+        }
+        (DataType::Integer, serde_json::Value::Bool(b)) => Some(i64::from(*b).into()),
+        (DataType::Float, serde_json::Value::Number(number)) => number.as_f64().map(Into::into),
+        (DataType::Float, serde_json::Value::String(s)) => {
+            s.trim().parse::<f64>().ok().map(Into::into)
+        }
+        (DataType::Text, _) => Some(value.to_string().into()),
+        (DataType::Boolean, serde_json::Value::Number(number)) => {
+            number.as_i64().map(|i| (i != 0).into())
+        }
+        (DataType::Date, serde_json::Value::String(s)) => {
+            Timestamp::parse(s).map(|date| date.date_string().into())
+        }
+        (DataType::Timestamp, serde_json::Value::String(s)) => {
+            Timestamp::parse(s).map(|timestamp| timestamp.timestamp_string().into())
+        }
+        (DataType::Interval, serde_json::Value::String(s)) => {
+            Interval::parse(s).map(|interval| interval.interval_string().into())
+        }
+        (DataType::Boolean, serde_json::Value::String(s)) => {
+            match s.trim().to_lowercase().as_str() {
+                "t" | "true" | "y" | "yes" | "on" | "1" => Some(true.into()),
+                "f" | "false" | "n" | "no" | "off" | "0" => Some(false.into()),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    cast_value.ok_or_else(|| FilterError::CannotCast {
+        value,
+        data_type: data_type.clone(),
+    })
+}
+
+// rounding halves to even like postgres, as long as the result fits
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn float_to_int(f: f64) -> Option<serde_json::Value> {
+    let rounded = f.round_ties_even();
+
+    (rounded >= i64::MIN as f64 && rounded < i64::MAX as f64).then(|| (rounded as i64).into())
+}
+
+// `not x`, where not null is still null
+fn not(value: &serde_json::Value) -> Result<serde_json::Value, QueryError> {
+    Ok(as_bool(value)
+        .map_err(QueryError::FilterError)?
+        .map_or(serde_json::Value::Null, |b| (!b).into()))
+}
+
+#[derive(Debug, PartialEq)]
+enum LikeToken {
+    Char(char),
+    // `_`
+    AnyChar,
+    // `%`
+    AnyString,
+}
+
+fn like(
+    value: &serde_json::Value,
+    pattern: &serde_json::Value,
+    negated: bool,
+    case_insensitive: bool,
+    escape: Option<char>,
+) -> Result<serde_json::Value, FilterError> {
+    if value.is_null() || pattern.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let (mut value, mut pattern) = (as_str(value)?.to_string(), as_str(pattern)?.to_string());
+
+    if case_insensitive {
+        value = value.to_lowercase();
+        pattern = pattern.to_lowercase();
+    }
+
+    let tokens = like_tokens(&pattern, escape)?;
+    let chars = value.chars().collect::<Vec<_>>();
+
+    Ok(serde_json::Value::Bool(
+        like_match(&chars, &tokens) != negated,
+    ))
+}
+
+fn like_tokens(pattern: &str, escape: Option<char>) -> Result<Vec<LikeToken>, FilterError> {
+    let mut chars = pattern.chars();
+    let mut tokens = vec![];
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if Some(c) == escape => LikeToken::Char(chars.next().ok_or_else(|| {
+                FilterError::LikePatternEndsWithEscape {
+                    pattern: pattern.to_string(),
+                }
+            })?),
+            '_' => LikeToken::AnyChar,
+            '%' => LikeToken::AnyString,
+            c => LikeToken::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+// walk both strings, remembering the last `%` so that when a match fails we
+// can go back and let it swallow one more character. linear apart from that
+// backtracking, rather than exponential like trying every split
+fn like_match(chars: &[char], tokens: &[LikeToken]) -> bool {
+    let (mut c, mut t) = (0, 0);
+    let mut last_any_string: Option<(usize, usize)> = None;
+
+    while c < chars.len() {
+        match tokens.get(t) {
+            Some(LikeToken::AnyChar) => {
+                c += 1;
+                t += 1;
+            }
+            Some(LikeToken::Char(expected)) if *expected == chars[c] => {
+                c += 1;
+                t += 1;
+            }
+            Some(LikeToken::AnyString) => {
+                last_any_string = Some((t, c));
+                t += 1;
+            }
+            _ => match last_any_string {
+                Some((any_t, any_c)) => {
+                    last_any_string = Some((any_t, any_c + 1));
+                    t = any_t + 1;
+                    c = any_c + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    tokens[t..]
+        .iter()
+        .all(|token| *token == LikeToken::AnyString)
+}
+
+// `x in (a, b)` is `x = a or x = b`, so it uses `equals`: numbers match by
+// value and rows, which are arrays, match item by item. it is true if any
+// item is equal, otherwise unknown if any comparison was, otherwise false
+fn in_list(
+    value: &serde_json::Value,
+    list: &[serde_json::Value],
+    negated: bool,
+) -> serde_json::Value {
+    let results: Vec<_> = list.iter().map(|item| equals(value, item)).collect();
+
+    if results.contains(&Some(true)) {
+        serde_json::Value::Bool(!negated)
+    } else if results.contains(&None) {
+        serde_json::Value::Null
+    } else {
+        serde_json::Value::Bool(negated)
+    }
+}
+
+// `x = ANY (array)` is true when the comparison holds for any item, `ALL`
+// when it holds for every item, so `ALL` of an empty array is true. when
+// nothing decides it but some comparison was unknown, so is the result
+pub fn quantified(
+    left: &serde_json::Value,
+    op: &Op,
+    quantifier: &Quantifier,
+    right: &serde_json::Value,
+) -> Result<serde_json::Value, FilterError> {
+    if right.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let results = as_array(right)?
+        .iter()
+        .map(|item| as_bool(&match_op(left, op, item)?))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let decided_by = matches!(quantifier, Quantifier::Any);
+
+    Ok(if results.contains(&Some(decided_by)) {
+        serde_json::Value::Bool(decided_by)
+    } else if results.contains(&None) {
+        serde_json::Value::Null
+    } else {
+        serde_json::Value::Bool(!decided_by)
+    })
+}
+
+// `@>`, true when every item on the right is somewhere on the left, null if
+// either side is null
+fn contains(
+    haystack: &serde_json::Value,
+    needles: &serde_json::Value,
+) -> Result<serde_json::Value, FilterError> {
+    if haystack.is_null() || needles.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let haystack = as_array(haystack)?;
+    let needles = as_array(needles)?;
+
+    Ok(serde_json::Value::Bool(
+        needles.iter().all(|needle| haystack.contains(needle)),
+    ))
+}
+
+// `array[index]`, counting from 1, anything out of range is null
+fn array_index(
+    value: &serde_json::Value,
+    index: &serde_json::Value,
+) -> Result<serde_json::Value, FilterError> {
+    if value.is_null() || index.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    let items = as_array(value)?;
+    let index = as_int(index)?;
+
+    let found = index
+        .checked_sub(1)
+        .and_then(|index| usize::try_from(index).ok())
+        .and_then(|index| items.get(index));
+
+    Ok(found.cloned().unwrap_or(serde_json::Value::Null))
+}
+
+// `->`, look up a key in an object or an index in an array, anything that
+// isn't there is null rather than an error
+fn json_get(value: &serde_json::Value, key: &serde_json::Value) -> serde_json::Value {
+    let found = match (value, key) {
+        (serde_json::Value::Object(map), serde_json::Value::String(key)) => map.get(key),
+        (serde_json::Value::Array(items), serde_json::Value::Number(index)) => index
+            .as_u64()
+            .and_then(|index| usize::try_from(index).ok())
+            .and_then(|index| items.get(index)),
+        _ => None,
+    };
+
+    found.cloned().unwrap_or(serde_json::Value::Null)
+}
+
+fn as_str(value: &serde_json::Value) -> Result<&str, FilterError> {
+    value.as_str().ok_or_else(|| FilterError::ExpectedString {
+        value: value.clone(),
+    })
+}
+
+fn as_array(value: &serde_json::Value) -> Result<&Vec<serde_json::Value>, FilterError> {
+    value.as_array().ok_or_else(|| FilterError::ExpectedArray {
+        value: value.clone(),
+    })
+}
+
+fn as_float(value: &serde_json::Value) -> Result<f64, FilterError> {
+    value.as_f64().ok_or_else(|| FilterError::ExpectedNumber {
+        value: value.clone(),
+    })
+}
+
 fn as_int(value: &serde_json::Value) -> Result<i64, FilterError> {
     value.as_i64().ok_or_else(|| FilterError::ExpectedInt {
         value: value.clone(),
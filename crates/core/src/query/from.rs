@@ -1,4 +1,8 @@
+use super::bloom::{BloomFilter, RuntimeFilter};
+use super::operator::{Context, Operator};
+use super::{parallel, tpch, QueryError};
 use crate::types::Cost;
+use crate::types::Profile;
 use crate::types::QueryStep;
 use crate::types::Row;
 use crate::types::Schema;
@@ -6,9 +10,17 @@ use crate::types::SchemaColumn;
 use crate::types::TableAlias;
 use crate::types::{Column, TableName};
 use serde_json::json;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+// every table that can be scanned, in the order `show tables` lists them
+pub const TABLE_NAMES: [&str; 8] = [
+    "animal", "species", "customer", "Album", "Artist", "Track", "orders", "lineitem",
+];
 
 // hard coded vec of column names for now
-fn schema(table_name: &TableName) -> Vec<Column> {
+pub fn schema(table_name: &TableName) -> Vec<Column> {
     match table_name.0.as_str() {
         "animal" => vec![
             "animal_id".into(),
@@ -16,6 +28,12 @@ fn schema(table_name: &TableName) -> Vec<Column> {
             "species_id".into(),
         ],
         "species" => vec!["species_id".into(), "species_name".into()],
+        "customer" => vec![
+            "customer_id".into(),
+            "customer_name".into(),
+            "active".into(),
+            "meta".into(),
+        ],
         "Album" => vec!["AlbumId".into(), "Title".into(), "ArtistId".into()],
         "Artist" => vec!["ArtistId".into(), "Name".into()],
         "Track" => vec![
@@ -29,22 +47,321 @@ fn schema(table_name: &TableName) -> Vec<Column> {
             "Bytes".into(),
             "UnitPrice".into(),
         ],
+        "orders" => vec![
+            "orderkey".into(),
+            "custkey".into(),
+            "orderstatus".into(),
+            "totalprice".into(),
+            "orderdate".into(),
+        ],
+        "lineitem" => vec![
+            "orderkey".into(),
+            "linenumber".into(),
+            "partkey".into(),
+            "quantity".into(),
+            "extendedprice".into(),
+            "discount".into(),
+            "returnflag".into(),
+            "shipdate".into(),
+        ],
         _ => todo!("unknown schema"),
     }
 }
 
-// scan of static values for now
-pub fn table_scan(table_name: &TableName, table_alias: Option<&TableAlias>) -> QueryStep {
+// columns are always qualified, by the table name if there is no alias
+fn table_schema(table_name: &TableName, table_alias: Option<&TableAlias>) -> Schema {
+    let table_alias = table_alias
+        .cloned()
+        .unwrap_or_else(|| TableAlias(table_name.0.as_str().into()));
+
     let columns = schema(table_name)
         .into_iter()
-        .map(|column| Column {
-            table_alias: table_alias.cloned(),
-            ..column
+        .map(|column| {
+            SchemaColumn::Column(Column {
+                table_alias: Some(table_alias.clone()),
+                ..column
+            })
         })
         .collect();
 
-    let raw = match table_name.0.as_str() {
-        "animal" => [(1, "horse", 1), (2, "dog", 1), (3, "snake", 2)]
+    Schema::new(columns)
+}
+
+// scan of static values for now. rows are copied out of the decoded table
+// one at a time as they are asked for, so a scan that is stopped early only
+// pays for the rows it returned. rows that the bloom filters of joins above
+// say can't match aren't copied at all
+pub struct Scan<'a> {
+    schema: Schema,
+    cursor: Cursor,
+    // the index of the column each filter checks
+    runtime_filters: Vec<(usize, Rc<RuntimeFilter>)>,
+    context: Rc<Context<'a>>,
+}
+
+pub fn table_scan<'a>(
+    table_name: &TableName,
+    table_alias: Option<&TableAlias>,
+    context: &Rc<Context<'a>>,
+) -> Result<Scan<'a>, QueryError> {
+    check_table_exists(table_name)?;
+
+    Ok(Scan {
+        schema: table_schema(table_name, table_alias),
+        cursor: Cursor::new(decoded_table(table_name)),
+        runtime_filters: vec![],
+        context: context.clone(),
+    })
+}
+
+// `schema` and the rows only know about the tables they were written for
+fn check_table_exists(table_name: &TableName) -> Result<(), QueryError> {
+    if TABLE_NAMES.contains(&table_name.0.as_str()) {
+        Ok(())
+    } else {
+        Err(QueryError::TableNotFound {
+            table_name: table_name.clone(),
+        })
+    }
+}
+
+// the filters whose build sides have been read
+fn blooms(runtime_filters: &[(usize, Rc<RuntimeFilter>)]) -> Vec<(usize, &BloomFilter)> {
+    runtime_filters
+        .iter()
+        .filter_map(|(index, filter)| Some((*index, filter.bloom()?)))
+        .collect()
+}
+
+// null keys never match, so they are dropped too
+fn might_match(row: &Row, blooms: &[(usize, &BloomFilter)]) -> bool {
+    blooms.iter().all(|(index, bloom)| {
+        let value = &row.items[*index];
+        !value.is_null() && bloom.might_contain(value)
+    })
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let blooms = blooms(&self.runtime_filters);
+
+        loop {
+            let row = self.cursor.remaining_rows()?.first()?;
+            let kept = might_match(row, &blooms).then(|| row.clone());
+            self.cursor.advance(1);
+            self.context.increment_rows_processed();
+
+            if let Some(row) = kept {
+                return Some(Ok(row));
+            }
+            self.context.with_cost(|cost| cost.bloom_filtered_rows += 1);
+        }
+    }
+}
+
+impl Operator for Scan<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    // big batches are copied out of the table by several threads at once.
+    // rows dropped by bloom filters are made up for by reading more, since
+    // a short batch means the scan is finished
+    fn next_batch(&mut self, size: usize) -> Result<Vec<Row>, QueryError> {
+        let blooms = blooms(&self.runtime_filters);
+        let mut batch = vec![];
+
+        while batch.len() < size {
+            let Some(rows) = self.cursor.remaining_rows() else {
+                break;
+            };
+            let rows = &rows[..rows.len().min(size - batch.len())];
+
+            let partitions = parallel::partition_count(rows.len(), self.context.threads());
+            let kept = parallel::map_partitions(parallel::chunks(rows, partitions), |rows| {
+                rows.iter()
+                    .filter(|row| might_match(row, &blooms))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+            let read = rows.len();
+            self.cursor.advance(read);
+
+            self.context.with_cost(|cost| {
+                cost.add_rows_processed(read);
+                cost.bloom_filtered_rows += (read - kept.len()) as u64;
+            });
+            batch.extend(kept);
+        }
+
+        Ok(batch)
+    }
+
+    fn push_runtime_filter(&mut self, filter: &Rc<RuntimeFilter>) {
+        if let Some(index) = self.schema.get_index_for_column(filter.column()) {
+            self.runtime_filters.push((index, filter.clone()));
+        }
+    }
+}
+
+// how many rows are decoded at once when a scan gets past the ones that
+// already have been
+const CHUNK_ROWS: usize = 1024;
+
+// rows for each table are decoded a chunk at a time, the first time a scan
+// gets to them, and kept for the life of the process. so a scan stopped early
+// by a limit only decodes as far as it read, and repeated scans (self joins,
+// the benchmarks, the logictests) don't parse the json or regenerate the
+// tpch data again
+struct DecodedTable {
+    columns: Vec<Column>,
+    chunks: Vec<Arc<[Row]>>,
+    // what is still to be decoded, `None` once all of it has been
+    undecoded: Option<Box<dyn Iterator<Item = serde_json::Value> + Send>>,
+}
+
+type SharedTable = Arc<Mutex<DecodedTable>>;
+
+impl DecodedTable {
+    // the chunk at `index`, decoding up to it if no scan has got that far.
+    // `None` past the end of the table
+    fn chunk(&mut self, index: usize) -> Option<Arc<[Row]>> {
+        while self.chunks.len() <= index {
+            let undecoded = self.undecoded.as_mut()?;
+            let chunk = undecoded
+                .take(CHUNK_ROWS)
+                .map(|raw| into_row(raw, &self.columns))
+                .collect::<Vec<_>>();
+
+            if chunk.is_empty() {
+                self.undecoded = None;
+                return None;
+            }
+            self.chunks.push(chunk.into());
+        }
+
+        self.chunks.get(index).cloned()
+    }
+
+    fn all_chunks(&mut self) -> &[Arc<[Row]>] {
+        self.chunk(usize::MAX);
+        &self.chunks
+    }
+}
+
+fn decoded_table(table_name: &TableName) -> SharedTable {
+    static DECODED: OnceLock<Mutex<HashMap<String, SharedTable>>> = OnceLock::new();
+
+    let mut decoded = lock(DECODED.get_or_init(Mutex::default));
+
+    decoded
+        .entry(table_name.0.clone())
+        .or_insert_with(|| {
+            Arc::new(Mutex::new(DecodedTable {
+                columns: schema(table_name),
+                chunks: vec![],
+                undecoded: Some(raw_rows(table_name)),
+            }))
+        })
+        .clone()
+}
+
+// nothing panics while holding these, but if something did the rows decoded
+// so far are still fine to use
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// where a scan has got to in a table. each chunk is shared, so reading one
+// doesn't hold up other scans of the same table
+struct Cursor {
+    table: SharedTable,
+    chunk: Arc<[Row]>,
+    next_chunk: usize,
+    position: usize,
+}
+
+impl Cursor {
+    fn new(table: SharedTable) -> Self {
+        Cursor {
+            table,
+            chunk: Arc::from(vec![]),
+            next_chunk: 0,
+            position: 0,
+        }
+    }
+
+    // the rows left in the current chunk, moving on to the next one when it
+    // runs out. `None` at the end of the table
+    fn remaining_rows(&mut self) -> Option<&[Row]> {
+        while self.position >= self.chunk.len() {
+            self.chunk = lock(&self.table).chunk(self.next_chunk)?;
+            self.next_chunk += 1;
+            self.position = 0;
+        }
+
+        Some(&self.chunk[self.position..])
+    }
+
+    fn advance(&mut self, rows: usize) {
+        self.position += rows;
+    }
+}
+
+// how many rows a table has, for estimating how big joins will be. tables
+// that don't exist are left for the scan to complain about
+pub fn row_count(table_name: &TableName) -> Option<usize> {
+    check_table_exists(table_name).ok()?;
+
+    let table = decoded_table(table_name);
+    let mut table = lock(&table);
+    Some(table.all_chunks().iter().map(|chunk| chunk.len()).sum())
+}
+
+// the items of a json array, parsed one at a time as they are asked for
+// rather than all at once. the bundled tables are arrays of objects, so
+// anything before an item that isn't part of it is a `[`, a `,` or space
+struct JsonArrayItems {
+    text: &'static str,
+    position: usize,
+}
+
+impl Iterator for JsonArrayItems {
+    type Item = serde_json::Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.text[self.position..]
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '[' || c == ',');
+        if rest.is_empty() || rest.starts_with(']') {
+            return None;
+        }
+
+        let mut items = serde_json::Deserializer::from_str(rest).into_iter();
+        let item = items.next()?.unwrap();
+        self.position = self.text.len() - rest.len() + items.byte_offset();
+
+        Some(item)
+    }
+}
+
+fn json_array(text: &'static str) -> Box<dyn Iterator<Item = serde_json::Value> + Send> {
+    Box::new(JsonArrayItems { text, position: 0 })
+}
+
+fn raw_rows(table_name: &TableName) -> Box<dyn Iterator<Item = serde_json::Value> + Send> {
+    let rows: Vec<serde_json::Value> = match table_name.0.as_str() {
+        "animal" => [
+            (1, "horse", Some(1)),
+            (2, "dog", Some(1)),
+            (3, "snake", Some(2)),
+            (4, "unicorn", None),
+        ]
             .iter()
             .map(|(id, name, species)| json!({ "animal_id": id, "animal_name": name, "species_id": species }))
             .collect(),
@@ -52,41 +369,113 @@ pub fn table_scan(table_name: &TableName, table_alias: Option<&TableAlias>) -> Q
             .iter()
             .map(|(id, name)| json!({"species_id": id, "species_name": name}))
             .collect(),
-        "Album" => {
-            let my_str = include_str!("../../static/Album.json");
-            serde_json::from_str::<Vec<serde_json::Value>>(my_str).unwrap()
-        },
-        "Artist" => {
-            let my_str = include_str!("../../static/Artist.json");
-            serde_json::from_str::<Vec<serde_json::Value>>(my_str).unwrap()
-        }
-        "Track" => {
-            let my_str = include_str!("../../static/Track.json");
-            serde_json::from_str::<Vec<serde_json::Value>>(my_str).unwrap()
-        }
+        "customer" => vec![
+            json!({"customer_id": 1, "customer_name": "Ada", "active": true, "meta": {"country": "UK", "age": 36, "tags": ["vip", "newsletter"]}}),
+            json!({"customer_id": 2, "customer_name": "Grace", "active": true, "meta": {"country": "US", "age": 45, "tags": ["newsletter"]}}),
+            json!({"customer_id": 3, "customer_name": "Linus", "active": false, "meta": {"country": "FI", "age": 28, "tags": []}}),
+            json!({"customer_id": 4, "customer_name": "Alan", "active": false, "meta": {"country": "UK", "address": {"city": "Manchester"}, "tags": ["vip"]}}),
+            json!({"customer_id": 5, "customer_name": "Barbara", "active": null, "meta": null}),
+        ],
+        "Album" => return json_array(include_str!("../../static/Album.json")),
+        "Artist" => return json_array(include_str!("../../static/Artist.json")),
+        "Track" => return json_array(include_str!("../../static/Track.json")),
+        "orders" => tpch::orders(tpch::DEFAULT_SCALE),
+        "lineitem" => tpch::lineitem(tpch::DEFAULT_SCALE),
         _ => todo!("table not found {table_name:?}"),
     };
 
+    Box::new(rows.into_iter())
+}
+
+pub fn show_tables() -> QueryStep {
     let mut cost = Cost::new();
 
-    let rows = raw
-        .into_iter()
-        .map(|raw| {
-            cost.increment_rows_processed();
-            into_row(raw, &columns)
+    let rows = TABLE_NAMES
+        .iter()
+        .inspect(|_| cost.increment_rows_processed())
+        .map(|table_name| Row {
+            items: vec![(*table_name).into()],
         })
         .collect();
 
     QueryStep {
-        schema: Schema {
-            columns: columns.into_iter().map(SchemaColumn::Column).collect(),
-        },
+        schema: Schema::new(vec![SchemaColumn::Named("table_name".into())]),
         rows,
         cost,
+        profile: Profile::default(),
+    }
+}
+
+// one row per column with its name, the type of the values in it and whether
+// any are null. tables don't declare types, so these are worked out from the
+// data
+pub fn describe(table_name: &TableName) -> Result<QueryStep, QueryError> {
+    check_table_exists(table_name)?;
+
+    let schema = table_schema(table_name, None);
+    let table = decoded_table(table_name);
+    let mut table = lock(&table);
+    let chunks = table.all_chunks();
+    let mut cost = Cost::new();
+
+    let described = schema
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(index, column)| {
+            cost.increment_rows_processed();
+
+            let values = chunks
+                .iter()
+                .flat_map(|chunk| chunk.iter())
+                .map(|row| &row.items[index]);
+
+            let mut types = values.clone().filter_map(value_type).collect::<Vec<_>>();
+            types.sort_unstable();
+            types.dedup();
+
+            let data_type = match types.as_slice() {
+                [] => "unknown",
+                [data_type] => data_type,
+                ["float", "integer"] => "float",
+                _ => "json",
+            };
+
+            Row {
+                items: vec![
+                    column.bare_name().into(),
+                    data_type.into(),
+                    values.clone().any(serde_json::Value::is_null).into(),
+                ],
+            }
+        })
+        .collect();
+
+    Ok(QueryStep {
+        schema: Schema::new(vec![
+            SchemaColumn::Named("column_name".into()),
+            SchemaColumn::Named("type".into()),
+            SchemaColumn::Named("nullable".into()),
+        ]),
+        rows: described,
+        cost,
+        profile: Profile::default(),
+    })
+}
+
+fn value_type(value: &serde_json::Value) -> Option<&'static str> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Bool(_) => Some("boolean"),
+        serde_json::Value::Number(number) if number.is_f64() => Some("float"),
+        serde_json::Value::Number(_) => Some("integer"),
+        serde_json::Value::String(_) => Some("text"),
+        serde_json::Value::Array(_) => Some("array"),
+        serde_json::Value::Object(_) => Some("json"),
     }
 }
 
-fn into_row(value: serde_json::Value, columns: &Vec<Column>) -> Row {
+fn into_row(value: serde_json::Value, columns: &[Column]) -> Row {
     let serde_json::Value::Object(mut map) = value else {
         panic!("what is this")
     };
@@ -95,7 +484,7 @@ fn into_row(value: serde_json::Value, columns: &Vec<Column>) -> Row {
 
     // collect items in order
     for column in columns {
-        let Some(item) = map.remove(&column.name) else {
+        let Some(item) = map.remove(&*column.name) else {
             panic!("could not find {}", column.name);
         };
 
@@ -104,3 +493,39 @@ fn into_row(value: serde_json::Value, columns: &Vec<Column>) -> Row {
 
     Row { items }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{json_array, raw_rows, schema, DecodedTable, CHUNK_ROWS};
+    use crate::types::TableName;
+
+    #[test]
+    fn test_json_array_items() {
+        let text = include_str!("../../static/Track.json");
+        let parsed = serde_json::from_str::<Vec<serde_json::Value>>(text).unwrap();
+
+        assert!(json_array(text).eq(parsed));
+        assert_eq!(json_array("[]").count(), 0);
+        assert_eq!(json_array(" [ {\"a\": [1, 2]} ,{\"a\": 3}\n]\n").count(), 2);
+    }
+
+    #[test]
+    fn test_tables_decode_as_they_are_read() {
+        // a table of its own, as other tests share the cached ones
+        let table_name = TableName("Track".into());
+        let mut table = DecodedTable {
+            columns: schema(&table_name),
+            chunks: vec![],
+            undecoded: Some(raw_rows(&table_name)),
+        };
+
+        assert_eq!(table.chunk(0).unwrap().len(), CHUNK_ROWS);
+        assert_eq!(table.chunks.len(), 1);
+
+        let total: usize = table.all_chunks().iter().map(|chunk| chunk.len()).sum();
+        assert_eq!(total, 3503);
+        assert_eq!(table.chunks.len(), 3503_usize.div_ceil(CHUNK_ROWS));
+        assert!(table.undecoded.is_none());
+        assert!(table.chunk(table.chunks.len()).is_none());
+    }
+}
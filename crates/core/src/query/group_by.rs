@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use super::filter::{distinct_key, evaluate_aggregate_expr, evaluate_expr, is_true};
+use super::project::unaggregated_column;
+use super::QueryError;
+use crate::types::{Cost, Expr, Row, Schema};
+
+// hash rows into groups by the values of the `group_by` expressions, then
+// evaluate each field once per group. groups come out in the order they were
+// first seen
+pub fn group_by(
+    rows: Vec<Row>,
+    schema: &Schema,
+    group_by: &[Expr],
+    having: Option<&Expr>,
+    fields: &[Expr],
+    cost: &mut Cost,
+) -> Result<Vec<Row>, QueryError> {
+    // anything outside an aggregate has to be grouped on, otherwise it could
+    // have a different value for each row in the group
+    if let Some(column) = fields
+        .iter()
+        .chain(having)
+        .find_map(|expr| unaggregated_column(expr, group_by))
+    {
+        return Err(QueryError::ColumnMustBeAggregated {
+            column_name: column.clone(),
+        });
+    }
+
+    let mut group_indexes: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<Vec<Row>> = vec![];
+
+    for row in rows {
+        cost.increment_rows_processed();
+
+        let key = group_by
+            .iter()
+            .map(|expr| evaluate_expr(&row, schema, expr))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // json values can't be hashed, but their text can. `1` and `1.0` are
+        // the same group, as they are equal
+        let key = distinct_key(&serde_json::Value::Array(key));
+
+        let index = *group_indexes.entry(key).or_insert_with(|| {
+            groups.push(vec![]);
+            groups.len() - 1
+        });
+        groups[index].push(row);
+    }
+
+    // grouping on nothing, like the `()` grouping set of a rollup, makes
+    // one group of everything even when there are no rows
+    if group_by.is_empty() && groups.is_empty() {
+        groups.push(vec![]);
+    }
+
+    let mut grouped_rows = vec![];
+
+    for group_rows in &groups {
+        if let Some(having) = having {
+            if !apply_having(group_rows, schema, having)? {
+                continue;
+            }
+        }
+
+        let items = fields
+            .iter()
+            .map(|field| evaluate_aggregate_expr(group_rows, schema, field))
+            .collect::<Result<_, _>>()?;
+
+        grouped_rows.push(Row { items });
+    }
+
+    Ok(grouped_rows)
+}
+
+// like a where clause, but over a whole group so it can use aggregates
+fn apply_having(group_rows: &[Row], schema: &Schema, having: &Expr) -> Result<bool, QueryError> {
+    is_true(&evaluate_aggregate_expr(group_rows, schema, having)?)
+}
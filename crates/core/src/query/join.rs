@@ -1,93 +1,798 @@
-use super::QueryError;
-use crate::types::Cost;
+use super::bloom::{BloomFilter, RuntimeFilter};
+use super::filter::{distinct_key, evaluate_expr, is_true, quantified};
+use super::operator::{BoxOperator, Context, Operator};
+use super::reorder::estimated_rows;
+use super::spill::{SpillFile, SpillReader};
+use super::{parallel, QueryError};
+use crate::types::Column;
+use crate::types::Expr;
 use crate::types::JoinOn;
 use crate::types::JoinType;
-use crate::types::QueryStep;
+use crate::types::Op;
+use crate::types::Quantifier;
+use crate::types::Query;
 use crate::types::Row;
 use crate::types::Schema;
-use std::collections::HashMap;
+use crate::types::SchemaColumn;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::DefaultHasher;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::rc::Rc;
 
-pub fn hash_join(
-    left_rows: Vec<Row>,
+// columns are told apart by their table alias, so joining a table to itself
+// needs at least one side to be given a different one
+fn check_table_aliases(left_schema: &Schema, right_schema: &Schema) -> Result<(), QueryError> {
+    let table_aliases = |schema: &Schema| {
+        schema
+            .columns()
+            .iter()
+            .filter_map(|schema_column| match schema_column {
+                SchemaColumn::Column(column) => column.table_alias.clone(),
+                SchemaColumn::Named(_) => None,
+            })
+            .collect::<HashSet<_>>()
+    };
+
+    let left_aliases = table_aliases(left_schema);
+
+    match table_aliases(right_schema)
+        .into_iter()
+        .find(|table_alias| left_aliases.contains(table_alias))
+    {
+        Some(table_alias) => Err(QueryError::DuplicateTableAlias { table_alias }),
+        None => Ok(()),
+    }
+}
+
+// which input the hash table is built from, the other one is streamed
+// through it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildSide {
+    Left,
+    Right,
+}
+
+// the table should be built from the smaller input, so as little as
+// possible is held in memory. when we can't tell, build on the right, since
+// the left side has usually been reordered to be the small one already
+pub fn build_side(left: &Query, right: &Query) -> BuildSide {
+    match (estimated_rows(left), estimated_rows(right)) {
+        (Some(left_rows), Some(right_rows)) if left_rows < right_rows => BuildSide::Left,
+        _ => BuildSide::Right,
+    }
+}
+
+// output order is deterministic: rows come out in probe input order, and
+// each probe row's matches follow in build input order. the build side is
+// read into a hash table on the first pull, then probe rows are streamed
+// through it. the hash map is only used for lookups, never iterated, so its
+// ordering can't leak into results. joined rows always have the left columns
+// first, whichever side was built on
+//
+// a left outer join built on the left keeps track of which left rows
+// matched, and the ones that didn't come out padded with nulls once the
+// probe side has run out
+//
+// a build side bigger than the join memory budget is spilled instead, grace
+// hash join style: both sides are split by key hash into partitions on disk,
+// so matching rows always end up in the same partition, then each partition
+// is joined on its own. rows then come out one partition after another, so
+// only an `order by` gives them an order
+//
+// unless probe rows that match nothing are kept, a bloom filter of the build
+// keys is handed down to the scans on the probe side once the build side has
+// been read, so most probe rows that can't match are never read into the join
+pub struct HashJoin<'a> {
+    probe: BoxOperator<'a>,
+    build: Option<BoxOperator<'a>>,
+    build_side: BuildSide,
+    probe_key: &'a Column,
+    build_key: &'a Column,
+    join_type: &'a JoinType,
+    build_schema: Schema,
+    build_rows: Vec<Row>,
+    // which build rows have found a match, only kept when unmatched build
+    // rows need padding
+    matched: Vec<bool>,
+    // padding for unmatched left rows
+    right_width: usize,
+    // positions of the build rows with each key hash, rather than copies of
+    // them, the rows are only copied once, into the output
+    table: HashMap<u64, Vec<usize>>,
+    pending: VecDeque<Row>,
+    // partitions still to be joined, and the probe rows of the current one,
+    // once the join has spilled
+    spilled: Option<VecDeque<(SpillFile, SpillFile)>>,
+    spilled_probe: Option<SpillReader>,
+    runtime_filter: Option<Rc<RuntimeFilter>>,
+    schema: Schema,
+    context: Rc<Context<'a>>,
+}
+
+// a spill is split into enough partitions for each to fit in the budget,
+// within reason
+const MAX_SPILL_PARTITIONS: usize = 64;
+
+impl<'a> HashJoin<'a> {
+    pub fn new(
+        left: BoxOperator<'a>,
+        right: BoxOperator<'a>,
+        on: &'a JoinOn,
+        join_type: &'a JoinType,
+        build_side: BuildSide,
+        context: &Rc<Context<'a>>,
+    ) -> Result<Self, QueryError> {
+        check_table_aliases(left.schema(), right.schema())?;
+        let (left_key, right_key) = join_keys(on, left.schema(), right.schema())?;
+
+        let right_width = right.schema().columns().len();
+        let mut schema = left.schema().clone();
+        schema.extend(right.schema().clone());
+
+        let (mut probe, build, probe_key, build_key) = match build_side {
+            BuildSide::Left => (right, left, right_key, left_key),
+            BuildSide::Right => (left, right, left_key, right_key),
+        };
+
+        // the filter is keyed on the column the scan will know it by
+        let pads_probe_rows =
+            matches!(join_type, JoinType::LeftOuter) && build_side == BuildSide::Right;
+        let probe_column = probe
+            .schema()
+            .get_index_for_column(probe_key)
+            .and_then(|index| match &probe.schema().columns()[index] {
+                SchemaColumn::Column(column) => Some(column.clone()),
+                SchemaColumn::Named(_) => None,
+            });
+        let runtime_filter = match probe_column {
+            Some(column) if !pads_probe_rows => {
+                let runtime_filter = Rc::new(RuntimeFilter::new(column));
+                probe.push_runtime_filter(&runtime_filter);
+                Some(runtime_filter)
+            }
+            _ => None,
+        };
+
+        Ok(HashJoin {
+            build_schema: build.schema().clone(),
+            probe,
+            build: Some(build),
+            build_side,
+            probe_key,
+            build_key,
+            join_type,
+            build_rows: vec![],
+            matched: vec![],
+            right_width,
+            table: HashMap::new(),
+            pending: VecDeque::new(),
+            spilled: None,
+            spilled_probe: None,
+            runtime_filter,
+            schema,
+            context: context.clone(),
+        })
+    }
+
+    // only left rows are kept when they match nothing, so it depends which
+    // side they are on
+    fn pads_probe_rows(&self) -> bool {
+        matches!(self.join_type, JoinType::LeftOuter) && self.build_side == BuildSide::Right
+    }
+
+    fn pads_build_rows(&self) -> bool {
+        matches!(self.join_type, JoinType::LeftOuter) && self.build_side == BuildSide::Left
+    }
+
+    fn build(&mut self, mut build: BoxOperator<'a>) -> Result<(), QueryError> {
+        self.build_rows = build.next_batch(usize::MAX)?;
+        self.context
+            .with_cost(|cost| cost.add_rows_processed(self.build_rows.len()));
+
+        // before anything is read from the probe side, including by a spill
+        if let Some(runtime_filter) = &self.runtime_filter {
+            let mut bloom = BloomFilter::new(self.build_rows.len());
+            for build_row in &self.build_rows {
+                let value = key_value(build_row, self.build_key, &self.build_schema)?;
+                if !value.is_null() {
+                    bloom.insert(value);
+                }
+            }
+            runtime_filter.set(bloom);
+        }
+
+        let size = self
+            .build_rows
+            .iter()
+            .map(Row::estimated_size)
+            .sum::<usize>();
+        let budget = self.context.config().join_memory_budget;
+        if size > budget {
+            let partitions = size.div_ceil(budget.max(1)).clamp(2, MAX_SPILL_PARTITIONS);
+            return self.spill(partitions);
+        }
+
+        self.index_build_rows()
+    }
+
+    // the keys are hashed by several threads at once for big inputs, then
+    // added to the table in order
+    fn index_build_rows(&mut self) -> Result<(), QueryError> {
+        let (build_key, build_schema) = (self.build_key, &self.build_schema);
+        let partitions = parallel::partition_count(self.build_rows.len(), self.context.threads());
+        let hashes = parallel::map_partitions(
+            parallel::chunks(&self.build_rows, partitions),
+            |build_rows| {
+                build_rows
+                    .iter()
+                    .map(|build_row| {
+                        let value = key_value(build_row, build_key, build_schema)?;
+
+                        // NULL never equals anything, so null keys can't find
+                        // a match
+                        Ok((!value.is_null()).then(|| key_hash(value)))
+                    })
+                    .collect::<Result<Vec<_>, QueryError>>()
+            },
+        );
+
+        let mut index = 0;
+        for partition in hashes {
+            for hash in partition? {
+                if let Some(hash) = hash {
+                    self.table.entry(hash).or_default().push(index);
+                }
+                index += 1;
+            }
+        }
+
+        if self.pads_build_rows() {
+            self.matched = vec![false; self.build_rows.len()];
+        }
+
+        Ok(())
+    }
+
+    // write both sides out to disk, split into partitions by key hash
+    fn spill(&mut self, partitions: usize) -> Result<(), QueryError> {
+        let partition_for = |hash: u64| usize::try_from(hash % partitions as u64).unwrap_or(0);
+        let (pads_probe_rows, pads_build_rows) = (self.pads_probe_rows(), self.pads_build_rows());
+
+        let mut spilled = (0..partitions)
+            .map(|_| Ok((SpillFile::create()?, SpillFile::create()?)))
+            .collect::<Result<Vec<_>, QueryError>>()?;
+        let (mut rows, mut bytes) = (0, 0);
+
+        // rows with null keys can't match, so they are only kept if they
+        // need padding, in whichever partition
+        for build_row in std::mem::take(&mut self.build_rows) {
+            let value = key_value(&build_row, self.build_key, &self.build_schema)?;
+
+            let partition = match (value.is_null(), pads_build_rows) {
+                (false, _) => partition_for(key_hash(value)),
+                (true, true) => 0,
+                (true, false) => continue,
+            };
+
+            let (_, build_file) = &mut spilled[partition];
+            bytes += build_file.push(&build_row)?;
+            rows += 1;
+        }
+
+        while let Some(probe_row) = self.probe.next() {
+            let probe_row = probe_row?;
+            let value = key_value(&probe_row, self.probe_key, self.probe.schema())?;
+
+            let partition = match (value.is_null(), pads_probe_rows) {
+                (false, _) => partition_for(key_hash(value)),
+                (true, true) => 0,
+                (true, false) => continue,
+            };
+
+            let (probe_file, _) = &mut spilled[partition];
+            bytes += probe_file.push(&probe_row)?;
+            rows += 1;
+        }
+
+        self.context.with_cost(|cost| {
+            cost.spilled_rows += rows;
+            cost.spilled_bytes += bytes as u64;
+        });
+
+        self.table.clear();
+        self.spilled = Some(spilled.into());
+        Ok(())
+    }
+
+    // the next row to probe with, from the input or from the spilled
+    // partitions, loading each partition's build rows as it is reached.
+    // unmatched build rows are padded as each lot of build rows is finished
+    // with
+    fn next_probe(&mut self) -> Option<Result<Row, QueryError>> {
+        if self.spilled.is_none() {
+            let probe_row = self.probe.next();
+            if probe_row.is_none() {
+                self.pad_unmatched_build_rows();
+            }
+            return probe_row;
+        }
+
+        loop {
+            if let Some(probe_row) = self.spilled_probe.as_mut().and_then(Iterator::next) {
+                return Some(probe_row);
+            }
+
+            self.pad_unmatched_build_rows();
+
+            let (probe_file, build_file) = self.spilled.as_mut()?.pop_front()?;
+            let loaded = build_file
+                .into_reader()
+                .and_then(Iterator::collect)
+                .and_then(|build_rows| {
+                    self.build_rows = build_rows;
+                    self.spilled_probe = Some(probe_file.into_reader()?);
+                    Ok(())
+                });
+            if let Err(error) = loaded {
+                return Some(Err(error));
+            }
+
+            self.table.clear();
+            if let Err(error) = self.index_build_rows() {
+                return Some(Err(error));
+            }
+        }
+    }
+
+    fn probe(&mut self, probe_row: Row) -> Result<(), QueryError> {
+        self.context.increment_rows_processed();
+
+        let value = key_value(&probe_row, self.probe_key, self.probe.schema())?;
+        let key = distinct_key(value);
+
+        let indexes = if value.is_null() {
+            None
+        } else {
+            self.table.get(&calculate_hash(&key))
+        };
+
+        let mut found = false;
+        for index in indexes.into_iter().flatten() {
+            let build_row = &self.build_rows[*index];
+
+            // the hash only narrows it down, the keys themselves must match
+            if distinct_key(key_value(build_row, self.build_key, &self.build_schema)?) != key {
+                continue;
+            }
+
+            found = true;
+            self.pending.push_back(match self.build_side {
+                BuildSide::Left => joined_row(build_row, &probe_row),
+                BuildSide::Right => joined_row(&probe_row, build_row),
+            });
+            if let Some(matched) = self.matched.get_mut(*index) {
+                *matched = true;
+            }
+        }
+
+        // no matches (or a null key), so only left outer joins keep the row
+        if !found && self.pads_probe_rows() {
+            self.pending
+                .push_back(padded_row(probe_row, self.right_width));
+        }
+
+        Ok(())
+    }
+
+    fn pad_unmatched_build_rows(&mut self) {
+        let matched = std::mem::take(&mut self.matched);
+        let build_rows = std::mem::take(&mut self.build_rows);
+
+        for (build_row, matched) in build_rows.into_iter().zip(matched) {
+            if !matched {
+                self.pending
+                    .push_back(padded_row(build_row, self.right_width));
+            }
+        }
+    }
+}
+
+impl Iterator for HashJoin<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(build) = self.build.take() {
+            if let Err(error) = self.build(build) {
+                return Some(Err(error));
+            }
+        }
+
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Some(Ok(row));
+            }
+
+            let probed = match self.next_probe() {
+                Some(Ok(probe_row)) => self.probe(probe_row),
+                Some(Err(error)) => Err(error),
+                // padded build rows may be all that's left
+                None => return self.pending.pop_front().map(Ok),
+            };
+            if let Err(error) = probed {
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+impl Operator for HashJoin<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    // rows with a key that can't match would only make joined rows with the
+    // same key, or padded ones with a null, so either input can be filtered
+    fn push_runtime_filter(&mut self, filter: &Rc<RuntimeFilter>) {
+        push_to_input(&mut self.probe, filter);
+        if let Some(build) = &mut self.build {
+            push_to_input(build, filter);
+        }
+    }
+}
+
+fn push_to_input(input: &mut BoxOperator, filter: &Rc<RuntimeFilter>) {
+    if input
+        .schema()
+        .get_index_for_column(filter.column())
+        .is_some()
+    {
+        input.push_runtime_filter(filter);
+    }
+}
+
+fn key_value<'r>(
+    row: &'r Row,
+    key: &Column,
+    schema: &Schema,
+) -> Result<&'r serde_json::Value, QueryError> {
+    row.get_column(key, schema)
+        .ok_or_else(|| QueryError::ColumnNotFoundInSchema {
+            column_name: key.clone(),
+        })
+}
+
+// `on Artist.ArtistId = Album.ArtistId` can be written either way around, so
+// pick whichever order lines up with the two inputs
+fn join_keys<'a>(
+    on: &'a JoinOn,
     left_schema: &Schema,
-    right_rows: Vec<Row>,
     right_schema: &Schema,
-    on: &JoinOn,
-    join_type: &JoinType,
-    mut cost: Cost,
-) -> Result<QueryStep, QueryError> {
-    let mut stuff = HashMap::new();
+) -> Result<(&'a Column, &'a Column), QueryError> {
+    let fits = |left: &Column, right: &Column| {
+        left_schema.get_index_for_column(left).is_some()
+            && right_schema.get_index_for_column(right).is_some()
+    };
+
+    if fits(&on.left, &on.right) {
+        Ok((&on.left, &on.right))
+    } else if fits(&on.right, &on.left) {
+        Ok((&on.right, &on.left))
+    } else {
+        let missing = if left_schema.get_index_for_column(&on.left).is_some()
+            || right_schema.get_index_for_column(&on.left).is_some()
+        {
+            &on.right
+        } else {
+            &on.left
+        };
+
+        Err(QueryError::ColumnNotFoundInSchema {
+            column_name: missing.clone(),
+        })
+    }
+}
+
+// every left row followed by every right row, so results are
+// deterministic. the right side is read in full on the first pull
+pub struct CrossJoinRows<'a> {
+    left: BoxOperator<'a>,
+    right: Option<BoxOperator<'a>>,
+    right_rows: Vec<Row>,
+    pending: VecDeque<Row>,
+    schema: Schema,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> CrossJoinRows<'a> {
+    pub fn new(
+        left: BoxOperator<'a>,
+        right: BoxOperator<'a>,
+        context: &Rc<Context<'a>>,
+    ) -> Result<Self, QueryError> {
+        check_table_aliases(left.schema(), right.schema())?;
+
+        let mut schema = left.schema().clone();
+        schema.extend(right.schema().clone());
 
-    // add all the relevent `on` values to map,
-    for left_row in &left_rows {
-        cost.increment_rows_processed();
-        let value = left_row.get_column(&on.left, left_schema).ok_or_else(|| {
-            QueryError::ColumnNotFoundInSchema {
-                column_name: on.left.clone(),
+        Ok(CrossJoinRows {
+            left,
+            right: Some(right),
+            right_rows: vec![],
+            pending: VecDeque::new(),
+            schema,
+            context: context.clone(),
+        })
+    }
+}
+
+impl Iterator for CrossJoinRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(mut right) = self.right.take() {
+            match right.next_batch(usize::MAX) {
+                Ok(right_rows) => self.right_rows = right_rows,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Some(Ok(row));
             }
-        })?;
 
-        stuff.insert(calculate_hash(value), vec![]);
+            let left_row = match self.left.next()? {
+                Ok(left_row) => left_row,
+                Err(error) => return Some(Err(error)),
+            };
+
+            for right_row in &self.right_rows {
+                self.context.increment_rows_processed();
+                self.pending.push_back(joined_row(&left_row, right_row));
+            }
+        }
     }
+}
 
-    // collect all the different right side values
-    for right_row in right_rows {
-        cost.increment_rows_processed();
-        let value = right_row
-            .get_column(&on.right, right_schema)
-            .ok_or_else(|| QueryError::ColumnNotFoundInSchema {
-                column_name: on.right.clone(),
-            })?;
+impl Operator for CrossJoinRows<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
 
-        // this assumes left join and ignores where there's no left match
-        if let Some(items) = stuff.get_mut(&calculate_hash(value)) {
-            items.push(right_row.clone());
+    fn push_runtime_filter(&mut self, filter: &Rc<RuntimeFilter>) {
+        push_to_input(&mut self.left, filter);
+        if let Some(right) = &mut self.right {
+            push_to_input(right, filter);
         }
     }
+}
 
-    let mut output_rows = vec![];
+// pairs every left row with every right row like `CrossJoinRows`, keeping
+// the pairs where `on` is true. a left outer join keeps left rows that
+// matched nothing, padded with nulls. this works for any condition, but
+// checks every pair, so equality on a column is left to `HashJoin`
+pub struct NestedLoopJoinRows<'a> {
+    left: BoxOperator<'a>,
+    right: Option<BoxOperator<'a>>,
+    on: &'a Expr,
+    join_type: &'a JoinType,
+    right_rows: Vec<Row>,
+    right_width: usize,
+    pending: VecDeque<Row>,
+    schema: Schema,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> NestedLoopJoinRows<'a> {
+    pub fn new(
+        left: BoxOperator<'a>,
+        right: BoxOperator<'a>,
+        on: &'a Expr,
+        join_type: &'a JoinType,
+        context: &Rc<Context<'a>>,
+    ) -> Result<Self, QueryError> {
+        check_table_aliases(left.schema(), right.schema())?;
+
+        let right_width = right.schema().columns().len();
+        let mut schema = left.schema().clone();
+        schema.extend(right.schema().clone());
+
+        Ok(NestedLoopJoinRows {
+            left,
+            right: Some(right),
+            on,
+            join_type,
+            right_rows: vec![],
+            right_width,
+            pending: VecDeque::new(),
+            schema,
+            context: context.clone(),
+        })
+    }
+
+    fn join_left_row(&mut self, left_row: Row) -> Result<(), QueryError> {
+        let mut matched = false;
 
-    for left_row in left_rows {
-        cost.increment_rows_processed();
-        let hash = calculate_hash(left_row.get_column(&on.left, left_schema).ok_or_else(|| {
-            QueryError::ColumnNotFoundInSchema {
-                column_name: on.left.clone(),
+        for right_row in &self.right_rows {
+            self.context.increment_rows_processed();
+
+            let row = joined_row(&left_row, right_row);
+            if is_true(&evaluate_expr(&row, &self.schema, self.on)?)? {
+                matched = true;
+                self.pending.push_back(row);
             }
-        })?);
+        }
 
-        if let Some(rhs) = stuff.get(&hash) {
-            if rhs.is_empty() {
-                // if left outer join
-                if let JoinType::LeftOuter = join_type {
-                    let mut whole_row = left_row.clone();
+        if !matched {
+            if let JoinType::LeftOuter = self.join_type {
+                self.pending
+                    .push_back(padded_row(left_row, self.right_width));
+            }
+        }
 
-                    // we can't find value, so add a bunch of nulls
-                    for _ in &right_schema.columns {
-                        whole_row.items.push(serde_json::Value::Null);
-                    }
-                    output_rows.push(whole_row);
-                }
-            } else {
-                for item in rhs {
-                    let mut whole_row = left_row.clone();
-                    whole_row.extend(item.clone());
-                    output_rows.push(whole_row);
-                }
+        Ok(())
+    }
+}
+
+impl Iterator for NestedLoopJoinRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(mut right) = self.right.take() {
+            match right.next_batch(usize::MAX) {
+                Ok(right_rows) => self.right_rows = right_rows,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Some(Ok(row));
+            }
+
+            let joined = match self.left.next()? {
+                Ok(left_row) => self.join_left_row(left_row),
+                Err(error) => Err(error),
+            };
+            if let Err(error) = joined {
+                return Some(Err(error));
             }
         }
     }
+}
 
-    let mut schema = left_schema.clone();
-    schema.extend(right_schema.clone());
+impl Operator for NestedLoopJoinRows<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
 
-    Ok(QueryStep {
-        rows: output_rows,
-        schema,
-        cost,
-    })
+    fn push_runtime_filter(&mut self, filter: &Rc<RuntimeFilter>) {
+        push_to_input(&mut self.left, filter);
+        if let Some(right) = &mut self.right {
+            push_to_input(right, filter);
+        }
+    }
+}
+
+// `expr in (subquery)`, keeping each left row whose value is one of the
+// subquery's. nulls behave like `in` with a list: a null value or a miss
+// against a subquery containing null is unknown, so the row is dropped either
+// way round. the subquery is only run once, when the first row is checked
+pub struct SemiJoin<'a> {
+    expr: &'a Expr,
+    subquery: Option<BoxOperator<'a>>,
+    negated: bool,
+    // json values can't be hashed, but their text can
+    values: HashSet<String>,
+}
+
+impl<'a> SemiJoin<'a> {
+    pub fn new(
+        expr: &'a Expr,
+        subquery: BoxOperator<'a>,
+        negated: bool,
+    ) -> Result<Self, QueryError> {
+        let found = subquery.schema().columns().len();
+        if found != 1 {
+            return Err(QueryError::SubqueryMustReturnOneColumn { found });
+        }
+
+        Ok(SemiJoin {
+            expr,
+            subquery: Some(subquery),
+            negated,
+            values: HashSet::new(),
+        })
+    }
+
+    pub fn keeps(&mut self, row: &Row, schema: &Schema) -> Result<bool, QueryError> {
+        if let Some(subquery) = self.subquery.take() {
+            for subquery_row in subquery {
+                self.values.insert(distinct_key(&subquery_row?.items[0]));
+            }
+        }
+
+        let value = evaluate_expr(row, schema, self.expr)?;
+        if value.is_null() {
+            return Ok(false);
+        }
+
+        Ok(if self.values.contains(&distinct_key(&value)) {
+            !self.negated
+        } else {
+            self.negated && !self.values.contains("null")
+        })
+    }
+}
+
+// `expr > all (subquery)`, comparing each row's value with every one of the
+// subquery's the same way as `expr > all (array)`. like `SemiJoin`, the
+// subquery is only run once, when the first row is checked
+pub struct QuantifiedSubquery<'a> {
+    expr: &'a Expr,
+    op: &'a Op,
+    quantifier: &'a Quantifier,
+    subquery: Option<BoxOperator<'a>>,
+    values: serde_json::Value,
+}
+
+impl<'a> QuantifiedSubquery<'a> {
+    pub fn new(
+        expr: &'a Expr,
+        op: &'a Op,
+        quantifier: &'a Quantifier,
+        subquery: BoxOperator<'a>,
+    ) -> Result<Self, QueryError> {
+        let found = subquery.schema().columns().len();
+        if found != 1 {
+            return Err(QueryError::SubqueryMustReturnOneColumn { found });
+        }
+
+        Ok(QuantifiedSubquery {
+            expr,
+            op,
+            quantifier,
+            subquery: Some(subquery),
+            values: serde_json::Value::Array(vec![]),
+        })
+    }
+
+    pub fn keeps(&mut self, row: &Row, schema: &Schema) -> Result<bool, QueryError> {
+        if let Some(subquery) = self.subquery.take() {
+            self.values = subquery
+                .map(|subquery_row| Ok(subquery_row?.items.swap_remove(0)))
+                .collect::<Result<_, QueryError>>()?;
+        }
+
+        let value = evaluate_expr(row, schema, self.expr)?;
+        is_true(
+            &quantified(&value, self.op, self.quantifier, &self.values)
+                .map_err(QueryError::FilterError)?,
+        )
+    }
+}
+
+// copy both sides straight into a row of the right size
+fn joined_row(left_row: &Row, right_row: &Row) -> Row {
+    let mut items = Vec::with_capacity(left_row.items.len() + right_row.items.len());
+    items.extend_from_slice(&left_row.items);
+    items.extend_from_slice(&right_row.items);
+    Row { items }
+}
+
+// a left row that matched nothing, with nulls where the right columns would be
+fn padded_row(left_row: Row, right_width: usize) -> Row {
+    let mut whole_row = left_row;
+    whole_row
+        .items
+        .resize(whole_row.items.len() + right_width, serde_json::Value::Null);
+    whole_row
+}
+
+// keys are hashed by value like `=` compares them, so `1` finds `1.0`
+fn key_hash(value: &serde_json::Value) -> u64 {
+    calculate_hash(&distinct_key(value))
 }
 
 fn calculate_hash<T: Hash>(t: &T) -> u64 {
@@ -95,3 +800,99 @@ fn calculate_hash<T: Hash>(t: &T) -> u64 {
     t.hash(&mut s);
     s.finish()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildSide, HashJoin};
+    use crate::query::operator::{Context, Rows};
+    use crate::query::EngineConfig;
+    use crate::types::{Column, JoinOn, JoinType, Row, Schema, SchemaColumn, TableAlias};
+    use serde_json::json;
+
+    fn column(table_alias: &str, name: &str) -> Column {
+        Column {
+            name: name.into(),
+            table_alias: Some(TableAlias(table_alias.into())),
+        }
+    }
+
+    fn rows(table_alias: &str, keys: &[serde_json::Value]) -> Rows {
+        let schema = Schema::new(vec![SchemaColumn::Column(column(table_alias, "key"))]);
+        let rows = keys
+            .iter()
+            .map(|key| Row {
+                items: vec![key.clone()],
+            })
+            .collect();
+        Rows::new(schema, rows)
+    }
+
+    #[test]
+    fn test_integer_keys_match_equal_floats() {
+        let on = JoinOn {
+            left: column("l", "key"),
+            right: column("r", "key"),
+        };
+
+        for build_side in [BuildSide::Left, BuildSide::Right] {
+            let join = HashJoin::new(
+                Box::new(rows("l", &[json!(1), json!(2.5), json!(3)])),
+                Box::new(rows("r", &[json!(1.0), json!(2.5), json!(3.5)])),
+                &on,
+                &JoinType::Inner,
+                build_side,
+                &Context::new(&EngineConfig::default(), None),
+            )
+            .unwrap();
+
+            let mut joined = join.map(|row| row.unwrap().items).collect::<Vec<_>>();
+            joined.sort_by_key(|items| serde_json::Value::Array(items.clone()).to_string());
+
+            assert_eq!(
+                joined,
+                vec![vec![json!(1), json!(1.0)], vec![json!(2.5), json!(2.5)]]
+            );
+        }
+    }
+
+    #[test]
+    fn test_colliding_hashes_do_not_match() {
+        let on = JoinOn {
+            left: column("l", "key"),
+            right: column("r", "key"),
+        };
+
+        for build_side in [BuildSide::Left, BuildSide::Right] {
+            let mut join = HashJoin::new(
+                Box::new(rows("l", &[json!(1), json!("a"), json!(2)])),
+                Box::new(rows("r", &[json!(2), json!(1), json!("b")])),
+                &on,
+                &JoinType::LeftOuter,
+                build_side,
+                &Context::new(&EngineConfig::default(), None),
+            )
+            .unwrap();
+
+            // pretend every key has the same hash, so the only thing telling
+            // them apart is checking the keys themselves
+            let build = join.build.take().unwrap();
+            join.build(build).unwrap();
+            let everything = join.table.values().flatten().copied().collect::<Vec<_>>();
+            for indexes in join.table.values_mut() {
+                indexes.clone_from(&everything);
+            }
+
+            let mut joined = join.map(|row| row.unwrap().items).collect::<Vec<_>>();
+            joined.sort_by_key(|items| serde_json::Value::Array(items.clone()).to_string());
+
+            assert_eq!(
+                joined,
+                vec![
+                    vec![json!("a"), json!(null)],
+                    vec![json!(1), json!(1)],
+                    vec![json!(2), json!(2)],
+                ]
+            );
+        }
+    }
+}
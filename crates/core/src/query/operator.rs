@@ -0,0 +1,475 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::time::Instant;
+
+use super::bloom::RuntimeFilter;
+use super::{filter, EngineConfig, QueryError};
+use crate::types::{Cost, Profile, QueryStep, Row, Schema};
+
+// every operator is an iterator of rows that pulls from its inputs only as
+// it needs to. rows stream through everything apart from sorts, groupings and
+// the build side of joins, so `limit 10` stops a scan after ten rows
+pub trait Operator: Iterator<Item = Result<Row, QueryError>> {
+    fn schema(&self) -> &Schema;
+
+    // up to `size` rows at once, for operators that can split them across
+    // threads. fewer than `size` means there are no more
+    fn next_batch(&mut self, size: usize) -> Result<Vec<Row>, QueryError> {
+        let mut rows = vec![];
+        while rows.len() < size {
+            match self.next() {
+                Some(row) => rows.push(row?),
+                None => break,
+            }
+        }
+        Ok(rows)
+    }
+
+    // a join's bloom filter, to drop rows that can't match as early as
+    // possible. operators that can hand it on towards a scan without
+    // changing which rows would match do, the rest ignore it
+    fn push_runtime_filter(&mut self, _filter: &Rc<RuntimeFilter>) {}
+}
+
+pub type BoxOperator<'a> = Box<dyn Operator + 'a>;
+
+// shared by every operator in a query
+pub struct Context<'a> {
+    config: EngineConfig,
+    cost: RefCell<Cost>,
+    // only set when tracing, since it means keeping every operator's rows
+    on_step: Option<RefCell<OnStep<'a>>>,
+}
+
+type OnStep<'a> = &'a mut dyn FnMut(&QueryStep);
+
+impl<'a> Context<'a> {
+    pub fn new(config: &EngineConfig, on_step: Option<OnStep<'a>>) -> Rc<Self> {
+        Rc::new(Context {
+            config: config.clone(),
+            cost: RefCell::new(Cost::new()),
+            on_step: on_step.map(RefCell::new),
+        })
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    pub fn threads(&self) -> usize {
+        self.config.threads
+    }
+
+    pub fn increment_rows_processed(&self) {
+        self.cost.borrow_mut().increment_rows_processed();
+    }
+
+    pub fn extend_cost(&self, cost: &Cost) {
+        self.cost.borrow_mut().extend(cost);
+    }
+
+    // for operators that hand their rows to a function that counts them
+    pub fn with_cost<T>(&self, f: impl FnOnce(&mut Cost) -> T) -> T {
+        f(&mut self.cost.borrow_mut())
+    }
+
+    pub fn cost(&self) -> Cost {
+        self.cost.borrow().clone()
+    }
+}
+
+// what an operator has done so far. the operator updates it as rows are
+// pulled through, and the profile is put together from it at the end
+pub struct Stats {
+    profile: RefCell<Profile>,
+    schema: Schema,
+    children: Vec<Rc<Stats>>,
+    finished: Cell<bool>,
+    // kept for `on_step`, only when tracing
+    rows: RefCell<Vec<Row>>,
+    span: tracing::Span,
+}
+
+impl Stats {
+    pub fn profile(&self) -> Profile {
+        let children: Vec<Profile> = self.children.iter().map(|child| child.profile()).collect();
+        let profile = self.profile.borrow();
+
+        Profile {
+            operator: profile.operator.clone(),
+            rows_in: children.iter().map(|child| child.rows_out).sum(),
+            rows_out: profile.rows_out,
+            elapsed: profile.elapsed,
+            memory_bytes: profile.memory_bytes,
+            children,
+        }
+    }
+
+    // operators finish when they run out of rows, or when the query is done
+    // with them, like the input of a `limit`. inputs finish first, so scans
+    // are traced before anything that reads them
+    fn finish(&self, context: &Context) {
+        if self.finished.replace(true) {
+            return;
+        }
+
+        for child in &self.children {
+            child.finish(context);
+        }
+
+        let profile = self.profile();
+        self.span.record("rows_in", profile.rows_in);
+        self.span.record("rows_out", profile.rows_out);
+
+        if let Some(on_step) = &context.on_step {
+            let step = QueryStep {
+                schema: self.schema.clone(),
+                rows: self.rows.take(),
+                cost: context.cost(),
+                profile,
+            };
+            (on_step.borrow_mut())(&step);
+        }
+    }
+}
+
+// wraps every operator to keep its stats up to date
+pub struct Measured<'a> {
+    inner: BoxOperator<'a>,
+    stats: Rc<Stats>,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> Measured<'a> {
+    pub fn new(
+        inner: BoxOperator<'a>,
+        operator_name: &'static str,
+        operator: String,
+        children: Vec<Rc<Stats>>,
+        context: &Rc<Context<'a>>,
+    ) -> Self {
+        let span = tracing::info_span!(
+            "run_query",
+            operator = operator_name,
+            rows_in = tracing::field::Empty,
+            rows_out = tracing::field::Empty,
+        );
+
+        let stats = Stats {
+            profile: RefCell::new(Profile {
+                operator,
+                ..Profile::default()
+            }),
+            schema: inner.schema().clone(),
+            children,
+            finished: Cell::new(false),
+            rows: RefCell::new(vec![]),
+            span,
+        };
+
+        Measured {
+            inner,
+            stats: Rc::new(stats),
+            context: context.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> Rc<Stats> {
+        self.stats.clone()
+    }
+
+    // report anything that didn't run out of rows on its own
+    pub fn finish(&self) {
+        self.stats.finish(&self.context);
+    }
+
+    fn record(&self, row: &Row) {
+        let mut profile = self.stats.profile.borrow_mut();
+        profile.rows_out += 1;
+        profile.memory_bytes += row.estimated_size();
+        if self.context.on_step.is_some() {
+            self.stats.rows.borrow_mut().push(row.clone());
+        }
+    }
+}
+
+impl Iterator for Measured<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.stats.finished.get() {
+            return None;
+        }
+
+        let started = Instant::now();
+        let next = self.stats.span.in_scope(|| self.inner.next());
+        self.stats.profile.borrow_mut().elapsed += started.elapsed();
+
+        if let Some(Ok(row)) = &next {
+            self.record(row);
+        }
+
+        // an error ends the query, so there is nothing more to come
+        if !matches!(next, Some(Ok(_))) {
+            self.finish();
+        }
+
+        next
+    }
+}
+
+impl Operator for Measured<'_> {
+    fn schema(&self) -> &Schema {
+        self.inner.schema()
+    }
+
+    fn next_batch(&mut self, size: usize) -> Result<Vec<Row>, QueryError> {
+        if self.stats.finished.get() {
+            return Ok(vec![]);
+        }
+
+        let started = Instant::now();
+        let rows = self.stats.span.in_scope(|| self.inner.next_batch(size));
+        self.stats.profile.borrow_mut().elapsed += started.elapsed();
+
+        match &rows {
+            Ok(rows) => {
+                for row in rows {
+                    self.record(row);
+                }
+                if rows.len() < size {
+                    self.finish();
+                }
+            }
+            Err(_) => self.finish(),
+        }
+
+        rows
+    }
+
+    fn push_runtime_filter(&mut self, filter: &Rc<RuntimeFilter>) {
+        self.inner.push_runtime_filter(filter);
+    }
+}
+
+// rows that have already been worked out, like `show tables`
+pub struct Rows {
+    schema: Schema,
+    rows: std::vec::IntoIter<Row>,
+}
+
+impl Rows {
+    pub fn new(schema: Schema, rows: Vec<Row>) -> Self {
+        Rows {
+            schema,
+            rows: rows.into_iter(),
+        }
+    }
+
+    pub fn from_step(step: QueryStep, context: &Context) -> Self {
+        context.extend_cost(&step.cost);
+        Rows::new(step.schema, step.rows)
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.next().map(Ok)
+    }
+}
+
+impl Operator for Rows {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+type BlockingFn<'a> = Box<dyn FnOnce(Vec<Row>, &mut Cost) -> Result<Vec<Row>, QueryError> + 'a>;
+
+// sorts and groupings need every row before they can return any, so the
+// whole input is pulled when the first row is asked for
+pub struct Blocking<'a> {
+    input: Option<BoxOperator<'a>>,
+    run: Option<BlockingFn<'a>>,
+    output: std::vec::IntoIter<Row>,
+    schema: Schema,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> Blocking<'a> {
+    pub fn new(
+        input: BoxOperator<'a>,
+        schema: Schema,
+        run: impl FnOnce(Vec<Row>, &mut Cost) -> Result<Vec<Row>, QueryError> + 'a,
+        context: &Rc<Context<'a>>,
+    ) -> Self {
+        Blocking {
+            input: Some(input),
+            run: Some(Box::new(run)),
+            output: vec![].into_iter(),
+            schema,
+            context: context.clone(),
+        }
+    }
+}
+
+impl Iterator for Blocking<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let (Some(mut input), Some(run)) = (self.input.take(), self.run.take()) {
+            let rows = match input.next_batch(usize::MAX) {
+                Ok(rows) => rows,
+                Err(error) => return Some(Err(error)),
+            };
+
+            match self.context.with_cost(|cost| run(rows, cost)) {
+                Ok(rows) => self.output = rows.into_iter(),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+
+        self.output.next().map(Ok)
+    }
+}
+
+impl Operator for Blocking<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+// skips `offset` rows, then stops pulling from its input after `limit`
+pub struct LimitRows<'a> {
+    input: BoxOperator<'a>,
+    offset: usize,
+    remaining: Option<usize>,
+}
+
+impl<'a> LimitRows<'a> {
+    pub fn new(input: BoxOperator<'a>, limit: Option<usize>, offset: Option<usize>) -> Self {
+        LimitRows {
+            input,
+            offset: offset.unwrap_or(0),
+            remaining: limit,
+        }
+    }
+}
+
+impl Iterator for LimitRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+
+        while self.offset > 0 {
+            self.offset -= 1;
+            if let Err(error) = self.input.next()? {
+                return Some(Err(error));
+            }
+        }
+
+        if let Some(remaining) = &mut self.remaining {
+            *remaining -= 1;
+        }
+
+        self.input.next()
+    }
+}
+
+impl Operator for LimitRows<'_> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+}
+
+// `select distinct`, the first of each set of identical rows
+pub struct DistinctRows<'a> {
+    input: BoxOperator<'a>,
+    // json values can't be hashed, but their text can. numbers are written
+    // the same way whether or not they are floats, so `1` and `1.0` match
+    seen: HashSet<String>,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> DistinctRows<'a> {
+    pub fn new(input: BoxOperator<'a>, context: &Rc<Context<'a>>) -> Self {
+        DistinctRows {
+            input,
+            seen: HashSet::new(),
+            context: context.clone(),
+        }
+    }
+}
+
+impl Iterator for DistinctRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let row = match self.input.next()? {
+                Ok(row) => row,
+                Err(error) => return Some(Err(error)),
+            };
+
+            self.context.increment_rows_processed();
+            if self
+                .seen
+                .insert(filter::distinct_key(&serde_json::Value::Array(
+                    row.items.clone(),
+                )))
+            {
+                return Some(Ok(row));
+            }
+        }
+    }
+}
+
+impl Operator for DistinctRows<'_> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+}
+
+// every row of `left` and then every row of `right`. rows are matched up by
+// position, so the names come from the left
+pub struct UnionRows<'a> {
+    left: BoxOperator<'a>,
+    right: BoxOperator<'a>,
+}
+
+impl<'a> UnionRows<'a> {
+    pub fn new(left: BoxOperator<'a>, right: BoxOperator<'a>) -> Result<Self, QueryError> {
+        let (left_count, right_count) = (
+            left.schema().columns().len(),
+            right.schema().columns().len(),
+        );
+        if left_count != right_count {
+            return Err(QueryError::UnionColumnCountMismatch {
+                left: left_count,
+                right: right_count,
+            });
+        }
+
+        Ok(UnionRows { left, right })
+    }
+}
+
+impl Iterator for UnionRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.left.next().or_else(|| self.right.next())
+    }
+}
+
+impl Operator for UnionRows<'_> {
+    fn schema(&self) -> &Schema {
+        self.left.schema()
+    }
+}
@@ -1,22 +1,41 @@
 use std::cmp::Ordering;
 
+use super::QueryError;
 use crate::types::{Cost, Order, OrderByExpr, Row, Schema};
 
+// `sort_by` is a stable sort, so rows that compare equal on every order by
+// expression keep the order they arrived in
 pub fn order_by(
     mut rows: Vec<Row>,
     schema: &Schema,
     order_by_exprs: &[OrderByExpr],
     cost: &mut Cost,
-) -> Vec<Row> {
+) -> Result<Vec<Row>, QueryError> {
+    // unqualified names can also be aliases or other named output columns
+    let indexes = order_by_exprs
+        .iter()
+        .map(|OrderByExpr { column, .. }| {
+            schema
+                .get_index_for_column(column)
+                .or_else(|| match column.table_alias {
+                    Some(_) => None,
+                    None => schema.get_index_for_named(&column.name),
+                })
+                .ok_or_else(|| QueryError::ColumnNotFoundInSchema {
+                    column_name: column.clone(),
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
     rows.sort_by(|row_a, row_b| {
         cost.increment_rows_processed();
-        order_by_exprs
-            .iter()
-            .fold(Ordering::Equal, |ordering, order_by_expr| {
+        order_by_exprs.iter().zip(&indexes).fold(
+            Ordering::Equal,
+            |ordering, (order_by_expr, index)| {
                 // if the ordering is still unknown
                 if ordering == Ordering::Equal {
-                    let a = row_a.get_column(&order_by_expr.column, schema).unwrap();
-                    let b = row_b.get_column(&order_by_expr.column, schema).unwrap();
+                    let a = &row_a.items[*index];
+                    let b = &row_b.items[*index];
 
                     let ordering = compare_values(a, b);
 
@@ -28,9 +47,10 @@ pub fn order_by(
                     // stick with the ordering we have
                     ordering
                 }
-            })
+            },
+        )
     });
-    rows
+    Ok(rows)
 }
 
 fn flip(ordering: Ordering) -> Ordering {
@@ -41,7 +61,10 @@ fn flip(ordering: Ordering) -> Ordering {
     }
 }
 
-fn compare_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+// nulls sort after everything else, like postgres, so they come last when
+// ascending and first when descending. values of different types, which can
+// turn up in json, are ordered by their type
+pub fn compare_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
     match (a, b) {
         (serde_json::Value::Null, serde_json::Value::Null) => Ordering::Equal,
         (serde_json::Value::Bool(a), serde_json::Value::Bool(b)) => a.cmp(b),
@@ -55,6 +78,26 @@ fn compare_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
             }
         }
         (serde_json::Value::String(a), serde_json::Value::String(b)) => a.cmp(b),
-        _ => todo!("Unsupported ordering"),
+        (serde_json::Value::Array(a), serde_json::Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (serde_json::Value::Object(_), serde_json::Value::Object(_)) => {
+            a.to_string().cmp(&b.to_string())
+        }
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+fn type_rank(value: &serde_json::Value) -> u8 {
+    match value {
+        serde_json::Value::Bool(_) => 0,
+        serde_json::Value::Number(_) => 1,
+        serde_json::Value::String(_) => 2,
+        serde_json::Value::Array(_) => 3,
+        serde_json::Value::Object(_) => 4,
+        serde_json::Value::Null => 5,
     }
 }
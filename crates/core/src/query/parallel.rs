@@ -0,0 +1,80 @@
+// inputs at least this big are split into partitions that are worked on by
+// their own threads, anything smaller isn't worth starting threads for
+pub const PARTITION_THRESHOLD: usize = 4096;
+
+// how many partitions `len` items should be split into
+pub fn partition_count(len: usize, threads: usize) -> usize {
+    if len < PARTITION_THRESHOLD {
+        1
+    } else {
+        threads.max(1)
+    }
+}
+
+// split `items` into `partitions` runs of about the same size, keeping their
+// order
+pub fn split<T>(items: Vec<T>, partitions: usize) -> Vec<Vec<T>> {
+    let partition_size = items.len().div_ceil(partitions.max(1)).max(1);
+    let mut items = items.into_iter();
+
+    (0..partitions)
+        .map(|_| items.by_ref().take(partition_size).collect::<Vec<_>>())
+        .filter(|partition| !partition.is_empty())
+        .collect()
+}
+
+// the same, for items that only need to be read
+pub fn chunks<T>(items: &[T], partitions: usize) -> std::slice::Chunks<'_, T> {
+    items.chunks(items.len().div_ceil(partitions.max(1)).max(1))
+}
+
+// each partition is worked on by its own thread and the results come back in
+// partition order, so putting them back together gives the same answer
+// however many partitions there are. a single partition is worked on by the
+// calling thread
+pub fn map_partitions<P: Send, R: Send>(
+    partitions: impl IntoIterator<Item = P>,
+    work: impl Fn(P) -> R + Sync,
+) -> Vec<R> {
+    let partitions = partitions.into_iter().collect::<Vec<_>>();
+    if partitions.len() < 2 {
+        return partitions.into_iter().map(work).collect();
+    }
+
+    let work = &work;
+    std::thread::scope(|scope| {
+        let workers = partitions
+            .into_iter()
+            .map(|partition| scope.spawn(move || work(partition)))
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("query worker panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunks, map_partitions, split};
+
+    #[test]
+    fn test_partitions_keep_order() {
+        let items = (0..10).collect::<Vec<_>>();
+
+        for partitions in 1..12 {
+            let doubled = map_partitions(split(items.clone(), partitions), |partition| {
+                partition.into_iter().map(|i| i * 2).collect::<Vec<_>>()
+            });
+
+            assert_eq!(doubled.concat(), (0..10).map(|i| i * 2).collect::<Vec<_>>());
+            assert_eq!(
+                map_partitions(chunks(&items, partitions), <[i32]>::len)
+                    .iter()
+                    .sum::<usize>(),
+                10
+            );
+        }
+    }
+}
@@ -1,8 +1,10 @@
-use std::collections::BTreeMap;
+use std::rc::Rc;
 
 use super::filter::evaluate_aggregate_expr;
 use super::filter::evaluate_expr;
+use super::operator::{BoxOperator, Context, Operator};
 use super::QueryError;
+use crate::types::Column;
 use crate::types::Cost;
 use crate::types::Expr;
 use crate::types::FunctionName;
@@ -19,7 +21,45 @@ pub fn project_schema(schema: &Schema, fields: &[Expr]) -> Result<Schema, QueryE
         columns.push(schema_column);
     }
 
-    Ok(Schema { columns })
+    Ok(Schema::new(columns))
+}
+
+// replace `*` and `artist.*` with the columns they stand for, in the order
+// they appear in the schema
+pub fn expand_wildcards(fields: &[Expr], schema: &Schema) -> Result<Vec<Expr>, QueryError> {
+    let mut expanded = vec![];
+
+    for field in fields {
+        let Expr::Wildcard { table_alias } = field else {
+            expanded.push(field.clone());
+            continue;
+        };
+
+        let columns = schema
+            .columns()
+            .iter()
+            .filter_map(|schema_column| match schema_column {
+                SchemaColumn::Column(column)
+                    if table_alias.is_none() || column.table_alias == *table_alias =>
+                {
+                    Some(Expr::Column {
+                        column: column.clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if let (Some(table_alias), true) = (table_alias, columns.is_empty()) {
+            return Err(QueryError::TableAliasNotFound {
+                table_alias: table_alias.clone(),
+            });
+        }
+
+        expanded.extend(columns);
+    }
+
+    Ok(expanded)
 }
 
 fn index_for_expr(field: &Expr, schema: &Schema) -> Result<SchemaColumn, QueryError> {
@@ -31,7 +71,7 @@ fn index_for_expr(field: &Expr, schema: &Schema) -> Result<SchemaColumn, QueryEr
                 }
             })?;
             schema
-                .columns
+                .columns()
                 .get(index)
                 .ok_or(QueryError::IndexNotFoundInSchema { index })
                 .cloned()
@@ -40,12 +80,27 @@ fn index_for_expr(field: &Expr, schema: &Schema) -> Result<SchemaColumn, QueryEr
             let name = format!("{literal}");
             Ok(SchemaColumn::Named(name))
         }
+        Expr::Parameter { index } => Ok(SchemaColumn::Named(format!("${index}"))),
         Expr::BinaryOperation { op, .. } => {
             let name = format!("{op}");
 
             Ok(SchemaColumn::Named(name))
         }
-        Expr::Nested { expr } => index_for_expr(expr, schema),
+        // a cast keeps the name of whatever is being cast
+        Expr::Nested { expr } | Expr::Cast { expr, .. } => index_for_expr(expr, schema),
+        Expr::Alias { alias, .. } => Ok(SchemaColumn::Named(alias.clone())),
+        Expr::Not { .. } => Ok(SchemaColumn::Named("not".into())),
+        Expr::Array { .. } => Ok(SchemaColumn::Named("array".into())),
+        Expr::Index { .. } => Ok(SchemaColumn::Named("index".into())),
+        Expr::InList { .. } | Expr::InSubquery { .. } => Ok(SchemaColumn::Named("in".into())),
+        Expr::Exists { .. } => Ok(SchemaColumn::Named("exists".into())),
+        Expr::Wildcard { .. } => Err(QueryError::WildcardNotSupportedHere),
+        Expr::IsNull { negated: false, .. } => Ok(SchemaColumn::Named("is_null".into())),
+        Expr::IsNull { negated: true, .. } => Ok(SchemaColumn::Named("is_not_null".into())),
+        Expr::Like { .. } => Ok(SchemaColumn::Named("like".into())),
+        Expr::Quantified { quantifier, .. } | Expr::QuantifiedSubquery { quantifier, .. } => {
+            Ok(SchemaColumn::Named(format!("{quantifier}")))
+        }
         Expr::FunctionCall { function_name, .. } => {
             let name = format!("{function_name}");
             Ok(SchemaColumn::Named(name))
@@ -53,80 +108,179 @@ fn index_for_expr(field: &Expr, schema: &Schema) -> Result<SchemaColumn, QueryEr
     }
 }
 
-fn is_aggregate_expr(expr: &Expr) -> bool {
+pub fn is_aggregate_expr(expr: &Expr) -> bool {
     match expr {
-        Expr::Column { .. } | Expr::Literal { .. } => false,
+        Expr::Column { .. }
+        | Expr::Literal { .. }
+        | Expr::Exists { .. }
+        | Expr::Wildcard { .. }
+        | Expr::Parameter { .. } => false,
         Expr::BinaryOperation { left, right, .. } => {
             is_aggregate_expr(left) || is_aggregate_expr(right)
         }
-        Expr::Nested { expr } => is_aggregate_expr(expr),
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. }
+        | Expr::QuantifiedSubquery { left: expr, .. } => is_aggregate_expr(expr),
+        Expr::Array { items } => items.iter().any(is_aggregate_expr),
+        Expr::Index { expr, index } => is_aggregate_expr(expr) || is_aggregate_expr(index),
+        Expr::InList { expr, list, .. } => {
+            is_aggregate_expr(expr) || list.iter().any(is_aggregate_expr)
+        }
+        Expr::Like { expr, pattern, .. } => is_aggregate_expr(expr) || is_aggregate_expr(pattern),
+        Expr::Quantified { left, right, .. } => is_aggregate_expr(left) || is_aggregate_expr(right),
         Expr::FunctionCall {
             function_name,
             args,
+            ..
         } => {
             let is_aggregate_function = match function_name {
                 FunctionName::Aggregate(_) => true,
+                FunctionName::Scalar(_) => false,
             };
             is_aggregate_function || args.iter().any(is_aggregate_expr)
         }
     }
 }
 
+// the first column used outside of an aggregate function, which can't be
+// given a single value when rows are aggregated together. expressions that
+// are grouped on have one value per group, so anything inside them is fine
+pub fn unaggregated_column<'a>(expr: &'a Expr, grouped: &[Expr]) -> Option<&'a Column> {
+    if grouped.contains(expr) {
+        return None;
+    }
+
+    let find = |expr| unaggregated_column(expr, grouped);
+
+    match expr {
+        Expr::Column { column } => Some(column),
+        Expr::Literal { .. }
+        | Expr::FunctionCall {
+            function_name: FunctionName::Aggregate(_),
+            ..
+        }
+        | Expr::Exists { .. }
+        | Expr::Wildcard { .. }
+        | Expr::Parameter { .. } => None,
+        Expr::FunctionCall {
+            function_name: FunctionName::Scalar(_),
+            args,
+            ..
+        } => args.iter().find_map(find),
+        Expr::BinaryOperation { left, right, .. }
+        | Expr::Quantified { left, right, .. }
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        }
+        | Expr::Index {
+            expr: left,
+            index: right,
+        } => find(left).or_else(|| find(right)),
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::InSubquery { expr, .. }
+        | Expr::QuantifiedSubquery { left: expr, .. } => find(expr),
+        Expr::Array { items } => items.iter().find_map(find),
+        Expr::InList { expr, list, .. } => find(expr).or_else(|| list.iter().find_map(find)),
+    }
+}
+
 pub fn project_fields(
     rows: &Vec<Row>,
     schema: &Schema,
     fields: &[Expr],
     cost: &mut Cost,
 ) -> Result<Vec<Row>, QueryError> {
-    let mut aggregate_results = BTreeMap::new();
-
-    // first work out the values for aggregate fields
-    for (index, field) in fields.iter().enumerate() {
-        if is_aggregate_expr(field) {
-            aggregate_results.insert(index, evaluate_aggregate_expr(rows, schema, field)?);
+    // if there are any aggregates we return one row of totals, so every other
+    // field has to be a constant
+    if fields.iter().any(is_aggregate_expr) {
+        if let Some(column) = fields
+            .iter()
+            .find_map(|field| unaggregated_column(field, &[]))
+        {
+            return Err(QueryError::ColumnMustBeAggregated {
+                column_name: column.clone(),
+            });
         }
+
+        let items = fields
+            .iter()
+            .map(|field| evaluate_aggregate_expr(rows, schema, field))
+            .collect::<Result<_, _>>()?;
+
+        return Ok(vec![Row { items }]);
     }
 
-    // if we only have aggregate fields, we only return one row of totals
-    // otherwise we return one for each row with the aggreagtes mixed in
-    if aggregate_results.len() == fields.len() {
-        let mut items = vec![];
-        for (index, _) in fields.iter().enumerate() {
-            let value = aggregate_results.get(&index).unwrap();
-            items.push(value.clone());
-        }
-        Ok(vec![Row { items }])
-    } else {
-        let mut projected_rows = vec![];
-
-        // then add regular fields
-        for row in rows {
-            cost.increment_rows_processed();
-            projected_rows.push(project_field_row(row, schema, fields, &aggregate_results)?);
+    let mut projected_rows = vec![];
+
+    for row in rows {
+        cost.increment_rows_processed();
+        projected_rows.push(project_field_row(row, schema, fields)?);
+    }
+
+    Ok(projected_rows)
+}
+
+// fields without aggregates are worked out one row at a time, as they are
+// pulled through
+pub struct ProjectRows<'a> {
+    input: BoxOperator<'a>,
+    fields: Vec<Expr>,
+    schema: Schema,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> ProjectRows<'a> {
+    pub fn new(
+        input: BoxOperator<'a>,
+        fields: Vec<Expr>,
+        schema: Schema,
+        context: &Rc<Context<'a>>,
+    ) -> Self {
+        ProjectRows {
+            input,
+            fields,
+            schema,
+            context: context.clone(),
         }
-        Ok(projected_rows)
     }
 }
 
-// filter columns out of a row
-pub fn project_field_row(
-    row: &Row,
-    schema: &Schema,
-    fields: &[Expr],
-    aggregate_results: &BTreeMap<usize, serde_json::Value>,
-) -> Result<Row, QueryError> {
-    let mut items = vec![];
-
-    // use aggregate value for field, or calculate for this particular row
-    for (index, field) in fields.iter().enumerate() {
-        let value = if let Some(agg_value) = aggregate_results.get(&index) {
-            agg_value.clone()
-        } else {
-            evaluate_expr(row, schema, field)?
+impl Iterator for ProjectRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.input.next()? {
+            Ok(row) => row,
+            Err(error) => return Some(Err(error)),
         };
 
-        items.push(value.clone());
+        self.context.increment_rows_processed();
+        Some(project_field_row(&row, self.input.schema(), &self.fields))
     }
+}
+
+impl Operator for ProjectRows<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+// filter columns out of a row
+pub fn project_field_row(row: &Row, schema: &Schema, fields: &[Expr]) -> Result<Row, QueryError> {
+    let items = fields
+        .iter()
+        .map(|field| evaluate_expr(row, schema, field))
+        .collect::<Result<_, _>>()?;
 
     Ok(Row { items })
 }
@@ -0,0 +1,514 @@
+use super::{from, from_columns, limit_count};
+use crate::types::{
+    Column, CrossJoin, Distinct, Explain, Expr, Filter, From, GroupBy, Join, JoinOn, JoinType,
+    Limit, NestedLoopJoin, Op, OrderBy, Project, Query, Sample, Schema, SchemaColumn, TableAlias,
+    Union,
+};
+
+// `from a, b where a.id = b.id` is planned as a filter over a cross join. an
+// equality between a column from each side becomes a hash join on them
+// instead, so the cross product is never made. equalities between two
+// columns of the same side are pushed down to it, where they can do the same
+// for its own cross joins. this runs before joins are reordered, so a chain
+// of them can be reordered too
+pub fn cross_joins_to_joins(query: &mut Query) {
+    if let Query::Filter(Filter { from, filter }) = query {
+        if let Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        }) = from.as_mut()
+        {
+            if let Some(rewritten) = join_on_equalities(left_from, right_from, filter) {
+                *query = rewritten;
+            }
+        }
+    }
+
+    match query {
+        Query::From(_) | Query::TableFunction(_) | Query::Describe(_) | Query::ShowTables => {}
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => {
+            cross_joins_to_joins(left_from);
+            cross_joins_to_joins(right_from);
+        }
+        Query::Filter(Filter {
+            from,
+            filter: Expr::InSubquery { subquery, .. } | Expr::QuantifiedSubquery { subquery, .. },
+        }) => {
+            cross_joins_to_joins(from);
+            cross_joins_to_joins(subquery);
+        }
+        Query::Filter(Filter { from, .. })
+        | Query::Sample(Sample { from, .. })
+        | Query::Project(Project { from, .. })
+        | Query::GroupBy(GroupBy { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::Limit(Limit { from, .. })
+        | Query::OrderBy(OrderBy { from, .. })
+        | Query::Explain(Explain { query: from }) => cross_joins_to_joins(from),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+// the cross join with its filter's column equalities moved into it, or
+// `None` if there were none that could be
+fn join_on_equalities(left_from: &Query, right_from: &Query, filter: &Expr) -> Option<Query> {
+    let schema = |query| {
+        Schema::new(
+            from_columns(query)
+                .into_iter()
+                .map(SchemaColumn::Column)
+                .collect(),
+        )
+    };
+    let (left_schema, right_schema) = (schema(left_from), schema(right_from));
+
+    // a column has to be found on exactly one side to know where it goes
+    let side = |column: &Column| match (
+        left_schema.get_index_for_column(column),
+        right_schema.get_index_for_column(column),
+    ) {
+        (Some(_), None) => Some(Side::Left),
+        (None, Some(_)) => Some(Side::Right),
+        _ => None,
+    };
+
+    let mut conjuncts = vec![];
+    flatten_and(filter, &mut conjuncts);
+
+    let mut on = None;
+    let mut left_filters = vec![];
+    let mut right_filters = vec![];
+    let mut remaining = vec![];
+
+    for conjunct in conjuncts {
+        let Expr::BinaryOperation {
+            left,
+            op: Op::Equals,
+            right,
+        } = conjunct
+        else {
+            remaining.push(conjunct.clone());
+            continue;
+        };
+        let (Expr::Column { column: left }, Expr::Column { column: right }) = (&**left, &**right)
+        else {
+            remaining.push(conjunct.clone());
+            continue;
+        };
+
+        match (side(left), side(right)) {
+            (Some(Side::Left), Some(Side::Right)) | (Some(Side::Right), Some(Side::Left))
+                if on.is_none() =>
+            {
+                on = Some(JoinOn {
+                    left: left.clone(),
+                    right: right.clone(),
+                });
+            }
+            (Some(Side::Left), Some(Side::Left)) => left_filters.push(conjunct.clone()),
+            (Some(Side::Right), Some(Side::Right)) => right_filters.push(conjunct.clone()),
+            _ => remaining.push(conjunct.clone()),
+        }
+    }
+
+    if on.is_none() && left_filters.is_empty() && right_filters.is_empty() {
+        return None;
+    }
+
+    let filtered = |from: &Query, filters: Vec<Expr>| match and_all(filters) {
+        Some(filter) => Query::Filter(Filter {
+            from: Box::new(from.clone()),
+            filter,
+        }),
+        None => from.clone(),
+    };
+    let left_from = Box::new(filtered(left_from, left_filters));
+    let right_from = Box::new(filtered(right_from, right_filters));
+
+    let joined = match on {
+        Some(on) => Query::Join(Join {
+            join_type: JoinType::Inner,
+            left_from,
+            right_from,
+            on,
+        }),
+        None => Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        }),
+    };
+
+    Some(filtered(&joined, remaining))
+}
+
+fn flatten_and<'a>(expr: &'a Expr, conjuncts: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::BinaryOperation {
+            left,
+            op: Op::And,
+            right,
+        } => {
+            flatten_and(left, conjuncts);
+            flatten_and(right, conjuncts);
+        }
+        Expr::Nested { expr: inner }
+            if matches!(**inner, Expr::BinaryOperation { op: Op::And, .. }) =>
+        {
+            flatten_and(inner, conjuncts);
+        }
+        _ => conjuncts.push(expr),
+    }
+}
+
+fn and_all(exprs: Vec<Expr>) -> Option<Expr> {
+    exprs
+        .into_iter()
+        .reduce(|left, right| Expr::BinaryOperation {
+            left: Box::new(left),
+            op: Op::And,
+            right: Box::new(right),
+        })
+}
+
+// inner joins give the same rows whatever order they run in, so a chain of
+// three or more tables is rebuilt to start from the smallest table and then
+// join whichever connected table is smallest next, keeping intermediate
+// results small. a projection on top puts the columns back in the order they
+// were written
+pub fn reorder_joins(query: &mut Query) {
+    if let Query::Join(join) = query {
+        if let Some(reordered) = reorder_join_chain(join) {
+            *query = reordered;
+            return;
+        }
+    }
+
+    match query {
+        Query::From(_) | Query::TableFunction(_) | Query::Describe(_) | Query::ShowTables => {}
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => {
+            reorder_joins(left_from);
+            reorder_joins(right_from);
+        }
+        // `in (select ...)` is run as part of the filter's plan, so it is
+        // reordered along with it
+        Query::Filter(Filter {
+            from,
+            filter: Expr::InSubquery { subquery, .. } | Expr::QuantifiedSubquery { subquery, .. },
+        }) => {
+            reorder_joins(from);
+            reorder_joins(subquery);
+        }
+        Query::Filter(Filter { from, .. })
+        | Query::Sample(Sample { from, .. })
+        | Query::Project(Project { from, .. })
+        | Query::GroupBy(GroupBy { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::Limit(Limit { from, .. })
+        | Query::OrderBy(OrderBy { from, .. })
+        | Query::Explain(Explain { query: from }) => reorder_joins(from),
+    }
+}
+
+// roughly how many rows a plan will give, for choosing which side of a join
+// to build a hash table from. tables are counted exactly, filters and
+// groupings are assumed to keep everything, and anything we can't guess
+// about, like table functions, is `None`
+pub fn estimated_rows(query: &Query) -> Option<usize> {
+    match query {
+        Query::From(from) => from::row_count(&from.table_name),
+        Query::TableFunction(_) | Query::Describe(_) | Query::ShowTables | Query::Explain(_) => {
+            None
+        }
+        // most joins match each row of the bigger side to a few of the
+        // other, like an album to its artist
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        }) => Some(estimated_rows(left_from)?.max(estimated_rows(right_from)?)),
+        Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        }) => Some(estimated_rows(left_from)?.saturating_mul(estimated_rows(right_from)?)),
+        Query::Union(Union { left, right }) => {
+            Some(estimated_rows(left)?.saturating_add(estimated_rows(right)?))
+        }
+        Query::Limit(Limit { from, limit, .. }) => {
+            let rows = estimated_rows(from)?;
+            Some(
+                limit
+                    .as_ref()
+                    .and_then(|limit| limit_count(limit).ok())
+                    .map_or(rows, |limit| rows.min(limit)),
+            )
+        }
+        Query::Filter(Filter { from, .. })
+        | Query::Sample(Sample { from, .. })
+        | Query::Project(Project { from, .. })
+        | Query::GroupBy(GroupBy { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::OrderBy(OrderBy { from, .. }) => estimated_rows(from),
+    }
+}
+
+// a table in the chain, with the alias its columns are qualified by
+struct Leaf<'a> {
+    from: &'a From,
+    alias: TableAlias,
+    columns: Vec<Column>,
+    rows: usize,
+}
+
+// a join condition with each side resolved to the leaf it comes from
+struct Condition {
+    left: (usize, Column),
+    right: (usize, Column),
+}
+
+fn reorder_join_chain(join: &Join) -> Option<Query> {
+    let mut leaves = vec![];
+    let mut conditions = vec![];
+    collect_inner_joins(join, &mut leaves, &mut conditions)?;
+
+    if leaves.len() < 3 {
+        return None;
+    }
+
+    let start = (0..leaves.len()).min_by_key(|index| leaves[*index].rows)?;
+
+    let mut joined = vec![start];
+    let mut used = vec![false; conditions.len()];
+    let mut plan = Query::From(leaves[start].from.clone());
+
+    while joined.len() < leaves.len() {
+        let connects = |leaf: usize, condition: &Condition| {
+            (condition.left.0 == leaf && joined.contains(&condition.right.0))
+                || (condition.right.0 == leaf && joined.contains(&condition.left.0))
+        };
+
+        let next = (0..leaves.len())
+            .filter(|leaf| !joined.contains(leaf))
+            .filter(|leaf| {
+                conditions
+                    .iter()
+                    .any(|condition| connects(*leaf, condition))
+            })
+            .min_by_key(|leaf| leaves[*leaf].rows)?;
+
+        let mut on = None;
+        let mut extra = vec![];
+
+        for (index, condition) in conditions.iter().enumerate() {
+            if used[index] || !connects(next, condition) {
+                continue;
+            }
+            used[index] = true;
+
+            let join_on = JoinOn {
+                left: condition.left.1.clone(),
+                right: condition.right.1.clone(),
+            };
+            if on.is_none() {
+                on = Some(join_on);
+            } else {
+                extra.push(join_on);
+            }
+        }
+
+        plan = Query::Join(Join {
+            join_type: JoinType::Inner,
+            left_from: Box::new(plan),
+            right_from: Box::new(Query::From(leaves[next].from.clone())),
+            on: on?,
+        });
+
+        // any other conditions between the new table and the ones already
+        // joined become filters on the joined rows
+        for JoinOn { left, right } in extra {
+            plan = Query::Filter(Filter {
+                from: Box::new(plan),
+                filter: Expr::BinaryOperation {
+                    left: Box::new(Expr::Column { column: left }),
+                    op: Op::Equals,
+                    right: Box::new(Expr::Column { column: right }),
+                },
+            });
+        }
+
+        joined.push(next);
+    }
+
+    // the order we were given was already the best one
+    if joined.iter().copied().eq(0..leaves.len()) {
+        return None;
+    }
+
+    let fields = leaves
+        .iter()
+        .flat_map(|leaf| &leaf.columns)
+        .map(|column| Expr::Column {
+            column: column.clone(),
+        })
+        .collect();
+
+    Some(Query::Project(Project {
+        from: Box::new(plan),
+        fields,
+    }))
+}
+
+// flatten a tree of inner joins over plain tables, giving up on anything else
+fn collect_inner_joins<'a>(
+    join: &'a Join,
+    leaves: &mut Vec<Leaf<'a>>,
+    conditions: &mut Vec<Condition>,
+) -> Option<()> {
+    let Join {
+        join_type: JoinType::Inner,
+        left_from,
+        right_from,
+        on,
+    } = join
+    else {
+        return None;
+    };
+
+    let left_start = leaves.len();
+    collect_side(left_from, leaves, conditions)?;
+    let right_start = leaves.len();
+    collect_side(right_from, leaves, conditions)?;
+
+    let left_side = left_start..right_start;
+    let right_side = right_start..leaves.len();
+
+    let left = resolve(&on.left, leaves, &left_side, &right_side)?;
+    let right = resolve(&on.right, leaves, &right_side, &left_side)?;
+
+    let sides = (left_side.contains(&left.0), right_side.contains(&right.0));
+    if sides != (true, true) && sides != (false, false) {
+        return None;
+    }
+
+    conditions.push(Condition { left, right });
+    Some(())
+}
+
+fn collect_side<'a>(
+    query: &'a Query,
+    leaves: &mut Vec<Leaf<'a>>,
+    conditions: &mut Vec<Condition>,
+) -> Option<()> {
+    match query {
+        Query::Join(join) => collect_inner_joins(join, leaves, conditions),
+        Query::From(from) => {
+            // columns are qualified by the table name if there is no alias,
+            // same as a table scan
+            let alias = from
+                .table_alias
+                .clone()
+                .unwrap_or_else(|| TableAlias(from.table_name.0.as_str().into()));
+
+            if leaves.iter().any(|leaf| leaf.alias == alias) {
+                return None;
+            }
+
+            let rows = from::row_count(&from.table_name)?;
+            let columns = from::schema(&from.table_name)
+                .into_iter()
+                .map(|column| Column {
+                    table_alias: Some(alias.clone()),
+                    ..column
+                })
+                .collect();
+
+            leaves.push(Leaf {
+                from,
+                alias,
+                columns,
+                rows,
+            });
+            Some(())
+        }
+        _ => None,
+    }
+}
+
+// find the leaf a join column belongs to, qualifying it with that leaf's
+// alias. unqualified columns are looked for on their own side of the join
+// first, and must only match one table
+fn resolve(
+    column: &Column,
+    leaves: &[Leaf],
+    side: &std::ops::Range<usize>,
+    other_side: &std::ops::Range<usize>,
+) -> Option<(usize, Column)> {
+    let index = if let Some(table_alias) = &column.table_alias {
+        leaves.iter().position(|leaf| &leaf.alias == table_alias)?
+    } else {
+        let find_unique = |range: &std::ops::Range<usize>| {
+            let mut matches = range.clone().filter(|index| {
+                leaves[*index]
+                    .columns
+                    .iter()
+                    .any(|leaf_column| leaf_column.name == column.name)
+            });
+            match (matches.next(), matches.next()) {
+                (Some(index), None) => Some(index),
+                _ => None,
+            }
+        };
+        find_unique(side).or_else(|| find_unique(other_side))?
+    };
+
+    Some((
+        index,
+        Column {
+            table_alias: Some(leaves[index].alias.clone()),
+            name: column.name.clone(),
+        },
+    ))
+}
@@ -0,0 +1,135 @@
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::operator::{BoxOperator, Context, Operator};
+use super::QueryError;
+use crate::types::{Row, Schema};
+
+// keep each row with a `percent` chance. the same seed always keeps the same
+// rows of the same input, and without one the clock is used
+pub struct SampleRows<'a> {
+    input: BoxOperator<'a>,
+    percent: f64,
+    rng: Rng,
+    context: Rc<Context<'a>>,
+}
+
+impl<'a> SampleRows<'a> {
+    pub fn new(
+        input: BoxOperator<'a>,
+        percent: f64,
+        seed: Option<u64>,
+        context: &Rc<Context<'a>>,
+    ) -> Self {
+        SampleRows {
+            input,
+            percent,
+            rng: Rng::new(seed.unwrap_or_else(clock_seed)),
+            context: context.clone(),
+        }
+    }
+}
+
+impl Iterator for SampleRows<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let row = self.input.next()?;
+            self.context.increment_rows_processed();
+            if row.is_err() || self.rng.next_f64() * 100.0 < self.percent {
+                return Some(row);
+            }
+        }
+    }
+}
+
+impl Operator for SampleRows<'_> {
+    fn schema(&self) -> &Schema {
+        self.input.schema()
+    }
+}
+
+// only the low bits change from run to run anyway
+#[allow(clippy::cast_possible_truncation)]
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
+
+// splitmix64, which is small and good enough for picking rows
+// https://prng.di.unimi.it/splitmix64.c
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in `[0, 1)`, from the top 53 bits so every value is exact
+    #[allow(clippy::cast_precision_loss)]
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rng, SampleRows};
+    use crate::query::operator::{Context, Rows};
+    use crate::query::EngineConfig;
+    use crate::types::{Row, Schema};
+
+    fn sample(count: i64, percent: f64, seed: Option<u64>) -> Vec<Row> {
+        let rows = (0..count)
+            .map(|i| Row {
+                items: vec![i.into()],
+            })
+            .collect();
+        let input = Box::new(Rows::new(Schema::new(vec![]), rows));
+
+        SampleRows::new(
+            input,
+            percent,
+            seed,
+            &Context::new(&EngineConfig::default(), None),
+        )
+        .collect::<Result<_, _>>()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_same_seed_same_rows() {
+        let first = sample(100, 50.0, Some(42));
+        let second = sample(100, 50.0, Some(42));
+
+        assert_eq!(first, second);
+        assert!(!first.is_empty() && first.len() < 100);
+    }
+
+    #[test]
+    fn test_all_or_nothing() {
+        assert_eq!(sample(10, 100.0, None).len(), 10);
+        assert!(sample(10, 0.0, None).is_empty());
+    }
+
+    #[test]
+    fn test_next_f64_in_range() {
+        let mut rng = Rng::new(0);
+
+        assert!((0..1000)
+            .map(|_| rng.next_f64())
+            .all(|f| (0.0..1.0).contains(&f)));
+    }
+}
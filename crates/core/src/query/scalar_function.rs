@@ -0,0 +1,379 @@
+use super::date::Timestamp;
+use super::QueryError;
+use crate::types::ScalarFunctionName;
+
+// a function that is worked out from already evaluated arguments, once for
+// each row. unless the function says otherwise a null argument gives a null
+// result
+pub trait ScalarFunction {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError>;
+}
+
+// `upper(name)`
+struct Upper;
+
+impl ScalarFunction for Upper {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [value] = expect_args(args)?;
+
+        map_string(&value, |s| s.to_uppercase().into())
+    }
+}
+
+// `lower(name)`
+struct Lower;
+
+impl ScalarFunction for Lower {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [value] = expect_args(args)?;
+
+        map_string(&value, |s| s.to_lowercase().into())
+    }
+}
+
+// `length(name)`, in characters rather than bytes
+struct Length;
+
+impl ScalarFunction for Length {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [value] = expect_args(args)?;
+
+        map_string(&value, |s| s.chars().count().into())
+    }
+}
+
+// `substr(name, start)` or `substr(name, start, count)`, characters are
+// counted from 1 like postgres, and a start before the first character still
+// uses up some of the count
+struct Substr;
+
+impl ScalarFunction for Substr {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let (value, start, count) = match <[_; 3]>::try_from(args) {
+            Ok([value, start, count]) => (value, start, Some(count)),
+            Err(args) => {
+                let [value, start] = expect_args(args)?;
+                (value, start, None)
+            }
+        };
+
+        if start.is_null() || count.as_ref().is_some_and(serde_json::Value::is_null) {
+            return Ok(serde_json::Value::Null);
+        }
+
+        let start = as_int(&start)?;
+        let end = count
+            .map(|count| as_int(&count).map(|count| start.saturating_add(count.max(0))))
+            .transpose()?
+            .unwrap_or(i64::MAX);
+
+        map_string(&value, |s| {
+            (1..)
+                .zip(s.chars())
+                .filter(|(position, _)| *position >= start && *position < end)
+                .map(|(_, c)| c)
+                .collect::<String>()
+                .into()
+        })
+    }
+}
+
+// `abs(x)`
+struct Abs;
+
+impl ScalarFunction for Abs {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [value] = expect_args(args)?;
+
+        map_number(&value, i64::checked_abs, f64::abs)
+    }
+}
+
+// `round(x)` or `round(x, digits)`, halves are rounded away from zero and
+// negative digits round to tens, hundreds and so on
+struct Round;
+
+impl ScalarFunction for Round {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let (value, digits) = match <[_; 2]>::try_from(args) {
+            Ok([value, digits]) => (value, digits),
+            Err(args) => {
+                let [value] = expect_args(args)?;
+                (value, 0.into())
+            }
+        };
+
+        if digits.is_null() {
+            return Ok(serde_json::Value::Null);
+        }
+
+        let scale =
+            10_f64.powi(
+                i32::try_from(as_int(&digits)?).map_err(|_| QueryError::TypeMismatch {
+                    expected: "i32".into(),
+                })?,
+            );
+        let round = |n: f64| (n * scale).round() / scale;
+
+        match value.as_i64() {
+            // whole numbers only change when rounding to tens or more
+            #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+            Some(i) if scale < 1.0 => Ok((round(i as f64) as i64).into()),
+            _ => map_number(&value, Some, round),
+        }
+    }
+}
+
+// `floor(x)`
+struct Floor;
+
+impl ScalarFunction for Floor {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [value] = expect_args(args)?;
+
+        map_number(&value, Some, f64::floor)
+    }
+}
+
+// `ceil(x)`
+struct Ceil;
+
+impl ScalarFunction for Ceil {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [value] = expect_args(args)?;
+
+        map_number(&value, Some, f64::ceil)
+    }
+}
+
+// `mod(x, y)`, the remainder has the sign of `x` like postgres
+struct Mod;
+
+impl ScalarFunction for Mod {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [left, right] = expect_args(args)?;
+
+        if left.is_null() || right.is_null() {
+            return Ok(serde_json::Value::Null);
+        }
+
+        if let (Some(left), Some(right)) = (left.as_i64(), right.as_i64()) {
+            return left
+                .checked_rem(right)
+                .map(Into::into)
+                .ok_or(QueryError::DivisionByZero);
+        }
+
+        let (left, right) = (as_float(&left)?, as_float(&right)?);
+        if right == 0.0 {
+            return Err(QueryError::DivisionByZero);
+        }
+
+        Ok((left % right).into())
+    }
+}
+
+// `now()`, the current UTC time as a timestamp
+struct Now;
+
+impl ScalarFunction for Now {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [] = expect_args(args)?;
+
+        Ok(Timestamp::now().timestamp_string().into())
+    }
+}
+
+// `date_part('year', x)`, one part of a date or timestamp as an integer
+struct DatePart;
+
+impl ScalarFunction for DatePart {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [field, value] = expect_args(args)?;
+
+        let Some(timestamp) = as_timestamp(&value)? else {
+            return Ok(serde_json::Value::Null);
+        };
+
+        match field {
+            serde_json::Value::String(field) => timestamp
+                .part(&field)
+                .map(Into::into)
+                .ok_or(QueryError::UnknownDatePart { field }),
+            serde_json::Value::Null => Ok(serde_json::Value::Null),
+            _ => Err(QueryError::TypeMismatch {
+                expected: "string".into(),
+            }),
+        }
+    }
+}
+
+// `strftime('%Y-%m', x)`, with the format first like sqlite
+struct Strftime;
+
+impl ScalarFunction for Strftime {
+    fn call(&self, args: Vec<serde_json::Value>) -> Result<serde_json::Value, QueryError> {
+        let [format, value] = expect_args(args)?;
+
+        let Some(timestamp) = as_timestamp(&value)? else {
+            return Ok(serde_json::Value::Null);
+        };
+
+        map_string(&format, |format| timestamp.format(format).into())
+    }
+}
+
+fn expect_args<const N: usize>(
+    args: Vec<serde_json::Value>,
+) -> Result<[serde_json::Value; N], QueryError> {
+    let found = args.len();
+    args.try_into()
+        .map_err(|_| QueryError::WrongNumberOfArguments { expected: N, found })
+}
+
+fn as_int(value: &serde_json::Value) -> Result<i64, QueryError> {
+    value.as_i64().ok_or_else(|| QueryError::TypeMismatch {
+        expected: "i64".into(),
+    })
+}
+
+fn as_float(value: &serde_json::Value) -> Result<f64, QueryError> {
+    value.as_f64().ok_or_else(|| QueryError::TypeMismatch {
+        expected: "number".into(),
+    })
+}
+
+// integers stay integers where they can, anything else is a float
+fn map_number(
+    value: &serde_json::Value,
+    ints: impl Fn(i64) -> Option<i64>,
+    floats: impl Fn(f64) -> f64,
+) -> Result<serde_json::Value, QueryError> {
+    if value.is_null() {
+        return Ok(serde_json::Value::Null);
+    }
+
+    if let Some(i) = value.as_i64().and_then(ints) {
+        return Ok(i.into());
+    }
+
+    Ok(floats(as_float(value)?).into())
+}
+
+fn as_timestamp(value: &serde_json::Value) -> Result<Option<Timestamp>, QueryError> {
+    match value {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::String(s) => {
+            Timestamp::parse(s)
+                .map(Some)
+                .ok_or_else(|| QueryError::TypeMismatch {
+                    expected: "date".into(),
+                })
+        }
+        _ => Err(QueryError::TypeMismatch {
+            expected: "date".into(),
+        }),
+    }
+}
+
+fn map_string(
+    value: &serde_json::Value,
+    f: impl Fn(&str) -> serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    match value {
+        serde_json::Value::String(s) => Ok(f(s)),
+        serde_json::Value::Null => Ok(serde_json::Value::Null),
+        _ => Err(QueryError::TypeMismatch {
+            expected: "string".into(),
+        }),
+    }
+}
+
+pub fn scalar_function(function_name: &ScalarFunctionName) -> &'static dyn ScalarFunction {
+    match function_name {
+        ScalarFunctionName::Upper => &Upper,
+        ScalarFunctionName::Lower => &Lower,
+        ScalarFunctionName::Length => &Length,
+        ScalarFunctionName::Substr => &Substr,
+        ScalarFunctionName::Abs => &Abs,
+        ScalarFunctionName::Round => &Round,
+        ScalarFunctionName::Floor => &Floor,
+        ScalarFunctionName::Ceil => &Ceil,
+        ScalarFunctionName::Mod => &Mod,
+        ScalarFunctionName::Now => &Now,
+        ScalarFunctionName::DatePart => &DatePart,
+        ScalarFunctionName::Strftime => &Strftime,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Length, Mod, Round, ScalarFunction, Substr, Upper};
+    use crate::query::QueryError;
+    use serde_json::json;
+
+    #[test]
+    fn test_upper_null_is_null() {
+        assert_eq!(Upper.call(vec![json!(null)]).unwrap(), json!(null));
+    }
+
+    #[test]
+    fn test_length_counts_characters() {
+        assert_eq!(Length.call(vec![json!("café")]).unwrap(), json!(4));
+    }
+
+    #[test]
+    fn test_substr() {
+        assert_eq!(
+            Substr.call(vec![json!("Fireball"), json!(5)]).unwrap(),
+            json!("ball")
+        );
+        assert_eq!(
+            Substr
+                .call(vec![json!("Fireball"), json!(0), json!(3)])
+                .unwrap(),
+            json!("Fi")
+        );
+    }
+
+    #[test]
+    fn test_substr_wrong_number_of_arguments() {
+        assert!(matches!(
+            Substr.call(vec![json!("Fireball")]),
+            Err(QueryError::WrongNumberOfArguments {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_upper_rejects_non_string() {
+        assert!(matches!(
+            Upper.call(vec![json!(1)]),
+            Err(QueryError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(Round.call(vec![json!(2.5)]).unwrap(), json!(3.0));
+        assert_eq!(Round.call(vec![json!(-2.5)]).unwrap(), json!(-3.0));
+        assert_eq!(
+            Round.call(vec![json!(1234), json!(-2)]).unwrap(),
+            json!(1200)
+        );
+        assert_eq!(
+            Round.call(vec![json!(1234), json!(2)]).unwrap(),
+            json!(1234)
+        );
+    }
+
+    #[test]
+    fn test_mod_by_zero() {
+        assert!(matches!(
+            Mod.call(vec![json!(1), json!(0)]),
+            Err(QueryError::DivisionByZero)
+        ));
+    }
+}
@@ -0,0 +1,384 @@
+use std::cmp::Ordering;
+
+use super::{filter, project};
+use crate::types::{
+    Column, CrossJoin, Distinct, Explain, Expr, Filter, GroupBy, Join, Limit, NestedLoopJoin, Op,
+    OrderBy, Project, Query, Row, Sample, Schema, Union,
+};
+
+// tidy up the predicate of every filter in the plan before it runs. `where`
+// drops rows whose predicate is null as well as false, which is what lets
+// conditions be folded away or turned into `false` without changing what
+// comes out
+pub fn simplify_filters(query: &mut Query) {
+    match query {
+        Query::From(_) | Query::TableFunction(_) | Query::Describe(_) | Query::ShowTables => {}
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => {
+            simplify_filters(left_from);
+            simplify_filters(right_from);
+        }
+        Query::Filter(Filter { from, filter }) => {
+            simplify_filters(from);
+            match filter {
+                Expr::InSubquery { subquery, .. } | Expr::QuantifiedSubquery { subquery, .. } => {
+                    simplify_filters(subquery);
+                }
+                _ => *filter = simplify(filter),
+            }
+        }
+        Query::Sample(Sample { from, .. })
+        | Query::Project(Project { from, .. })
+        | Query::GroupBy(GroupBy { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::Limit(Limit { from, .. })
+        | Query::OrderBy(OrderBy { from, .. })
+        | Query::Explain(Explain { query: from }) => simplify_filters(from),
+    }
+}
+
+// only used where null and false mean the same thing, so not under a `not`
+fn simplify(expr: &Expr) -> Expr {
+    match expr {
+        Expr::BinaryOperation { op: Op::And, .. } => simplify_and(expr),
+        Expr::BinaryOperation {
+            left,
+            op: Op::Or,
+            right,
+        } => match (simplify(left), simplify(right)) {
+            (left, right) if is_true(&left) || is_true(&right) => true_expr(),
+            (left, right) if is_false(&left) => right,
+            (left, right) if is_false(&right) => left,
+            (left, right) => Expr::BinaryOperation {
+                left: Box::new(left),
+                op: Op::Or,
+                right: Box::new(right),
+            },
+        },
+        Expr::Nested { expr: inner } => match simplify(inner) {
+            literal @ Expr::Literal { .. } => literal,
+            inner => Expr::Nested {
+                expr: Box::new(inner),
+            },
+        },
+        _ => match constant(expr) {
+            Some(true) => true_expr(),
+            Some(false) => false_expr(),
+            None => expr.clone(),
+        },
+    }
+}
+
+// the conditions joined by `and` are folded one at a time: ones that are
+// always true are dropped, one that is never true makes them all false, and
+// comparisons of a column with a literal are merged into one range per column
+// so `x > 5 and x < 3` is found to be empty
+fn simplify_and(expr: &Expr) -> Expr {
+    let mut conjuncts = vec![];
+    flatten_and(expr, &mut conjuncts);
+
+    // each column's comparisons are merged into the range at the position of
+    // its first one, so everything else stays in the order it was written
+    let mut parts = vec![];
+    for conjunct in conjuncts {
+        let simplified = simplify(conjunct);
+        if is_true(&simplified) {
+            continue;
+        }
+        if is_false(&simplified) {
+            return false_expr();
+        }
+
+        let Some((column, op, literal)) = column_comparison(&simplified) else {
+            parts.push(Part::Other(simplified));
+            continue;
+        };
+
+        let existing = parts.iter_mut().find_map(|part| match part {
+            Part::Range(existing, range) if *existing == column => Some(range),
+            _ => None,
+        });
+        if let Some(range) = existing {
+            range.add(op, literal);
+        } else {
+            let mut range = Range::default();
+            range.add(op, literal);
+            parts.push(Part::Range(column, range));
+        }
+    }
+
+    if parts
+        .iter()
+        .any(|part| matches!(part, Part::Range(_, range) if range.is_empty()))
+    {
+        return false_expr();
+    }
+
+    parts
+        .into_iter()
+        .flat_map(|part| match part {
+            Part::Range(column, range) => range.into_conjuncts(&column),
+            Part::Other(expr) => vec![expr],
+        })
+        .reduce(|left, right| Expr::BinaryOperation {
+            left: Box::new(left),
+            op: Op::And,
+            right: Box::new(right),
+        })
+        .unwrap_or_else(true_expr)
+}
+
+enum Part {
+    Range(Column, Range),
+    Other(Expr),
+}
+
+fn flatten_and<'a>(expr: &'a Expr, conjuncts: &mut Vec<&'a Expr>) {
+    match expr {
+        Expr::BinaryOperation {
+            left,
+            op: Op::And,
+            right,
+        } => {
+            flatten_and(left, conjuncts);
+            flatten_and(right, conjuncts);
+        }
+        Expr::Nested { expr: inner }
+            if matches!(**inner, Expr::BinaryOperation { op: Op::And, .. }) =>
+        {
+            flatten_and(inner, conjuncts);
+        }
+        _ => conjuncts.push(expr),
+    }
+}
+
+// whether a predicate that mentions no columns keeps rows, worked out once.
+// anything that can't be worked out without a row, like `exists`, or that
+// fails, is left for the filter so the error comes from running the query
+fn constant(expr: &Expr) -> Option<bool> {
+    if project::unaggregated_column(expr, &[]).is_some() || has_subquery(expr) {
+        return None;
+    }
+
+    let empty_row = Row { items: vec![] };
+    filter::apply_predicate(&empty_row, &Schema::new(vec![]), expr).ok()
+}
+
+fn has_subquery(expr: &Expr) -> bool {
+    match expr {
+        Expr::Exists { .. } | Expr::InSubquery { .. } | Expr::QuantifiedSubquery { .. } => true,
+        Expr::Column { .. }
+        | Expr::Literal { .. }
+        | Expr::Wildcard { .. }
+        | Expr::Parameter { .. } => false,
+        Expr::FunctionCall { args, .. } => args.iter().any(has_subquery),
+        Expr::BinaryOperation { left, right, .. }
+        | Expr::Quantified { left, right, .. }
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        }
+        | Expr::Index {
+            expr: left,
+            index: right,
+        } => has_subquery(left) || has_subquery(right),
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. } => has_subquery(expr),
+        Expr::Array { items } => items.iter().any(has_subquery),
+        Expr::InList { expr, list, .. } => has_subquery(expr) || list.iter().any(has_subquery),
+    }
+}
+
+// `column op literal`, or the other way round with the operator flipped
+fn column_comparison(expr: &Expr) -> Option<(Column, Op, serde_json::Value)> {
+    let Expr::BinaryOperation { left, op, right } = expr else {
+        return None;
+    };
+
+    let (column, op, literal) = match (&**left, &**right) {
+        (Expr::Column { column }, Expr::Literal { literal }) => (column, op.clone(), literal),
+        (Expr::Literal { literal }, Expr::Column { column }) => {
+            let flipped = match op {
+                Op::GreaterThan => Op::LessThan,
+                Op::GreaterThanOrEqual => Op::LessThanOrEqual,
+                Op::LessThan => Op::GreaterThan,
+                Op::LessThanOrEqual => Op::GreaterThanOrEqual,
+                other => other.clone(),
+            };
+            (column, flipped, literal)
+        }
+        _ => return None,
+    };
+
+    let comparable = matches!(
+        literal,
+        serde_json::Value::Number(_) | serde_json::Value::String(_)
+    );
+    let range_op = matches!(
+        op,
+        Op::Equals | Op::GreaterThan | Op::GreaterThanOrEqual | Op::LessThan | Op::LessThanOrEqual
+    );
+
+    (comparable && range_op).then(|| (column.clone(), op, literal.clone()))
+}
+
+// the values a column can take given every comparison made with it. each
+// bound is a value and whether the value itself is allowed
+#[derive(Default)]
+struct Range {
+    lower: Option<(serde_json::Value, bool)>,
+    upper: Option<(serde_json::Value, bool)>,
+    // literals that can't be compared with each other, like a number and
+    // some text, are kept as they were written
+    unmerged: Vec<(Op, serde_json::Value)>,
+}
+
+impl Range {
+    fn add(&mut self, op: Op, literal: serde_json::Value) {
+        let bounds_before = (self.lower.clone(), self.upper.clone());
+
+        let merged = match op {
+            Op::Equals => {
+                tighten(&mut self.lower, &literal, true, Ordering::Greater)
+                    && tighten(&mut self.upper, &literal, true, Ordering::Less)
+            }
+            Op::GreaterThan => tighten(&mut self.lower, &literal, false, Ordering::Greater),
+            Op::GreaterThanOrEqual => tighten(&mut self.lower, &literal, true, Ordering::Greater),
+            Op::LessThan => tighten(&mut self.upper, &literal, false, Ordering::Less),
+            Op::LessThanOrEqual => tighten(&mut self.upper, &literal, true, Ordering::Less),
+            _ => false,
+        };
+
+        if !merged {
+            (self.lower, self.upper) = bounds_before;
+            self.unmerged.push((op, literal));
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        let (Some((lower, lower_inclusive)), Some((upper, upper_inclusive))) =
+            (&self.lower, &self.upper)
+        else {
+            return false;
+        };
+
+        match filter::compare(lower, upper) {
+            Ok(Some(Ordering::Greater)) => true,
+            Ok(Some(Ordering::Equal)) => !(*lower_inclusive && *upper_inclusive),
+            _ => false,
+        }
+    }
+
+    fn into_conjuncts(self, column: &Column) -> Vec<Expr> {
+        let comparison = |op, literal| Expr::BinaryOperation {
+            left: Box::new(Expr::Column {
+                column: column.clone(),
+            }),
+            op,
+            right: Box::new(Expr::Literal { literal }),
+        };
+
+        let mut conjuncts = vec![];
+        match (self.lower, self.upper) {
+            (Some((lower, true)), Some((upper, true)))
+                if matches!(filter::compare(&lower, &upper), Ok(Some(Ordering::Equal))) =>
+            {
+                conjuncts.push(comparison(Op::Equals, lower));
+            }
+            (lower, upper) => {
+                if let Some((lower, inclusive)) = lower {
+                    let op = if inclusive {
+                        Op::GreaterThanOrEqual
+                    } else {
+                        Op::GreaterThan
+                    };
+                    conjuncts.push(comparison(op, lower));
+                }
+                if let Some((upper, inclusive)) = upper {
+                    let op = if inclusive {
+                        Op::LessThanOrEqual
+                    } else {
+                        Op::LessThan
+                    };
+                    conjuncts.push(comparison(op, upper));
+                }
+            }
+        }
+
+        conjuncts.extend(
+            self.unmerged
+                .into_iter()
+                .map(|(op, literal)| comparison(op, literal)),
+        );
+        conjuncts
+    }
+}
+
+// keep whichever of the bound and the literal allows fewer values. `tighter`
+// is the ordering a literal has to have with the bound to replace it, and an
+// equal literal only keeps the bound inclusive if both are. false if the two
+// can't be compared
+fn tighten(
+    bound: &mut Option<(serde_json::Value, bool)>,
+    literal: &serde_json::Value,
+    inclusive: bool,
+    tighter: Ordering,
+) -> bool {
+    let Some((value, value_inclusive)) = bound else {
+        *bound = Some((literal.clone(), inclusive));
+        return true;
+    };
+
+    match filter::compare(literal, value) {
+        Ok(Some(ordering)) if ordering == tighter => {
+            *bound = Some((literal.clone(), inclusive));
+            true
+        }
+        Ok(Some(Ordering::Equal)) => {
+            *value_inclusive = *value_inclusive && inclusive;
+            true
+        }
+        Ok(Some(_)) => true,
+        _ => false,
+    }
+}
+
+fn is_true(expr: &Expr) -> bool {
+    *expr == true_expr()
+}
+
+fn is_false(expr: &Expr) -> bool {
+    *expr == false_expr()
+}
+
+fn true_expr() -> Expr {
+    Expr::Literal {
+        literal: serde_json::Value::Bool(true),
+    }
+}
+
+fn false_expr() -> Expr {
+    Expr::Literal {
+        literal: serde_json::Value::Bool(false),
+    }
+}
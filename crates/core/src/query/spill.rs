@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::QueryError;
+use crate::types::Row;
+
+// rows that didn't fit in memory, written to a temporary file as one json
+// array per line. the file is deleted once it has been read back, or when it
+// is dropped without being read
+pub struct SpillFile {
+    path: TempPath,
+    writer: BufWriter<File>,
+}
+
+impl SpillFile {
+    pub fn create() -> Result<Self, QueryError> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "lets-build-a-database-{}-{}.spill",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::create(&path).map_err(QueryError::SpillFailed)?;
+
+        Ok(SpillFile {
+            path: TempPath(path),
+            writer: BufWriter::new(file),
+        })
+    }
+
+    // returns how many bytes were written
+    pub fn push(&mut self, row: &Row) -> Result<usize, QueryError> {
+        let mut line = serde_json::Value::Array(row.items.clone()).to_string();
+        line.push('\n');
+
+        self.writer
+            .write_all(line.as_bytes())
+            .map_err(QueryError::SpillFailed)?;
+
+        Ok(line.len())
+    }
+
+    pub fn into_reader(self) -> Result<SpillReader, QueryError> {
+        let SpillFile { path, mut writer } = self;
+        writer.flush().map_err(QueryError::SpillFailed)?;
+
+        let file = File::open(&path.0).map_err(QueryError::SpillFailed)?;
+
+        Ok(SpillReader {
+            lines: BufReader::new(file).lines(),
+            _path: path,
+        })
+    }
+}
+
+// the rows of a `SpillFile`, in the order they were written
+pub struct SpillReader {
+    lines: Lines<BufReader<File>>,
+    _path: TempPath,
+}
+
+impl Iterator for SpillReader {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(error) => return Some(Err(QueryError::SpillFailed(error))),
+        };
+
+        Some(match serde_json::from_str(&line) {
+            Ok(serde_json::Value::Array(items)) => Ok(Row { items }),
+            Ok(_) | Err(_) => Err(QueryError::SpillFailed(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "spilled row is not a json array",
+            ))),
+        })
+    }
+}
+
+struct TempPath(PathBuf);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        // nothing can be done about a file that won't go away
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpillFile;
+    use crate::types::Row;
+    use serde_json::json;
+
+    #[test]
+    fn test_rows_read_back_in_order() {
+        let rows = vec![
+            Row {
+                items: vec![json!(1), json!("one"), json!(null)],
+            },
+            Row {
+                items: vec![json!(2.5), json!({"a": [1, 2]}), json!(true)],
+            },
+        ];
+
+        let mut spill = SpillFile::create().unwrap();
+        for row in &rows {
+            assert!(spill.push(row).unwrap() > 0);
+        }
+
+        let read = spill
+            .into_reader()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(read, rows);
+    }
+}
@@ -0,0 +1,246 @@
+use super::operator::Context;
+use super::{run_step, QueryError};
+use crate::types::{
+    Column, CrossJoin, Distinct, Expr, Filter, From, GroupBy, Join, Limit, NestedLoopJoin, OrderBy,
+    Project, Query, QueryStep, Row, Sample, Schema, TableAlias, TableFunctionCall, Union,
+};
+
+// `exists (subquery)`, keeping each row for which the subquery returns any
+// rows. columns qualified by a table that isn't in the subquery are taken
+// from the outer row, so the subquery is run again for each row with those
+// values filled in. a subquery that doesn't refer to the outer row at all is
+// only run once
+pub struct Exists<'a> {
+    subquery: &'a Query,
+    negated: bool,
+    inner_aliases: Vec<TableAlias>,
+    uncorrelated: Option<bool>,
+}
+
+impl<'a> Exists<'a> {
+    pub fn new(subquery: &'a Query, negated: bool) -> Self {
+        let mut inner_aliases = vec![];
+        collect_aliases(subquery, &mut inner_aliases);
+
+        Exists {
+            subquery,
+            negated,
+            inner_aliases,
+            uncorrelated: None,
+        }
+    }
+
+    pub fn keeps(
+        &mut self,
+        row: &Row,
+        schema: &Schema,
+        context: &Context,
+    ) -> Result<bool, QueryError> {
+        let mut bound = self.subquery.clone();
+        let is_correlated = bind_query(&mut bound, &self.inner_aliases, row, schema);
+
+        let found = if let (false, Some(found)) = (is_correlated, self.uncorrelated) {
+            found
+        } else {
+            // only the first row is needed to know there are any
+            let QueryStep {
+                rows: found_rows,
+                cost: subquery_cost,
+                ..
+            } = run_step(
+                &Query::Limit(Limit {
+                    from: Box::new(bound),
+                    limit: Some(Expr::Literal { literal: 1.into() }),
+                    offset: None,
+                }),
+                &Context::new(context.config(), None),
+            )?;
+            context.extend_cost(&subquery_cost);
+
+            let found = !found_rows.is_empty();
+            if !is_correlated {
+                self.uncorrelated = Some(found);
+            }
+            found
+        };
+
+        Ok(found != self.negated)
+    }
+}
+
+// the aliases that columns inside the subquery can be qualified by
+fn collect_aliases(query: &Query, aliases: &mut Vec<TableAlias>) {
+    match query {
+        Query::From(From {
+            table_name,
+            table_alias,
+        }) => aliases.push(
+            table_alias
+                .clone()
+                .unwrap_or_else(|| TableAlias(table_name.0.as_str().into())),
+        ),
+        Query::TableFunction(TableFunctionCall {
+            function_name,
+            table_alias,
+            ..
+        }) => aliases.push(
+            table_alias
+                .clone()
+                .unwrap_or_else(|| TableAlias(function_name.to_string().into())),
+        ),
+        Query::Describe(_) | Query::ShowTables | Query::Explain(_) => {}
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => {
+            collect_aliases(left_from, aliases);
+            collect_aliases(right_from, aliases);
+        }
+        Query::Filter(Filter { from, .. })
+        | Query::Sample(Sample { from, .. })
+        | Query::Project(Project { from, .. })
+        | Query::GroupBy(GroupBy { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::Limit(Limit { from, .. })
+        | Query::OrderBy(OrderBy { from, .. }) => collect_aliases(from, aliases),
+    }
+}
+
+// replace references to the outer row with their values, returning whether
+// there were any
+fn bind_query(query: &mut Query, inner: &[TableAlias], row: &Row, schema: &Schema) -> bool {
+    let bind_all = |exprs: &mut [Expr]| {
+        exprs.iter_mut().fold(false, |bound, expr| {
+            bind_expr(expr, inner, row, schema) | bound
+        })
+    };
+
+    match query {
+        Query::From(_) | Query::Describe(_) | Query::ShowTables | Query::Explain(_) => false,
+        Query::TableFunction(TableFunctionCall { args, .. }) => bind_all(args),
+        Query::Join(Join {
+            left_from,
+            right_from,
+            ..
+        })
+        | Query::CrossJoin(CrossJoin {
+            left_from,
+            right_from,
+        })
+        | Query::Union(Union {
+            left: left_from,
+            right: right_from,
+        }) => {
+            bind_query(left_from, inner, row, schema) | bind_query(right_from, inner, row, schema)
+        }
+        Query::NestedLoopJoin(NestedLoopJoin {
+            left_from,
+            right_from,
+            on,
+            ..
+        }) => {
+            bind_expr(on, inner, row, schema)
+                | bind_query(left_from, inner, row, schema)
+                | bind_query(right_from, inner, row, schema)
+        }
+        Query::Filter(Filter { from, filter }) => {
+            bind_expr(filter, inner, row, schema) | bind_query(from, inner, row, schema)
+        }
+        Query::Project(Project { from, fields }) => {
+            bind_all(fields) | bind_query(from, inner, row, schema)
+        }
+        Query::GroupBy(GroupBy {
+            from,
+            group_by,
+            having,
+            fields,
+        }) => {
+            bind_all(group_by)
+                | bind_all(fields)
+                | having
+                    .as_mut()
+                    .is_some_and(|having| bind_expr(having, inner, row, schema))
+                | bind_query(from, inner, row, schema)
+        }
+        Query::Sample(Sample { from, .. })
+        | Query::Distinct(Distinct { from })
+        | Query::Limit(Limit { from, .. })
+        | Query::OrderBy(OrderBy { from, .. }) => bind_query(from, inner, row, schema),
+    }
+}
+
+fn bind_expr(expr: &mut Expr, inner: &[TableAlias], row: &Row, schema: &Schema) -> bool {
+    let bind = |expr: &mut Expr| bind_expr(expr, inner, row, schema);
+
+    match expr {
+        Expr::Column {
+            column:
+                column @ Column {
+                    table_alias: Some(_),
+                    ..
+                },
+        } => {
+            let is_outer = column
+                .table_alias
+                .as_ref()
+                .is_some_and(|table_alias| !inner.contains(table_alias));
+
+            match row.get_column(column, schema) {
+                Some(value) if is_outer => {
+                    *expr = Expr::Literal {
+                        literal: value.clone(),
+                    };
+                    true
+                }
+                _ => false,
+            }
+        }
+        Expr::Column { .. }
+        | Expr::Literal { .. }
+        | Expr::Wildcard { .. }
+        | Expr::Parameter { .. } => false,
+        Expr::BinaryOperation { left, right, .. }
+        | Expr::Quantified { left, right, .. }
+        | Expr::Like {
+            expr: left,
+            pattern: right,
+            ..
+        }
+        | Expr::Index {
+            expr: left,
+            index: right,
+        } => bind(left) | bind(right),
+        Expr::Nested { expr }
+        | Expr::Not { expr }
+        | Expr::Alias { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. } => bind(expr),
+        Expr::FunctionCall { args: items, .. } | Expr::Array { items } => items
+            .iter_mut()
+            .fold(false, |bound, item| bind(item) | bound),
+        Expr::InList { expr, list, .. } => list
+            .iter_mut()
+            .fold(bind(expr), |bound, item| bind(item) | bound),
+        Expr::InSubquery { expr, subquery, .. }
+        | Expr::QuantifiedSubquery {
+            left: expr,
+            subquery,
+            ..
+        } => bind(expr) | bind_query(subquery, inner, row, schema),
+        Expr::Exists { subquery, .. } => bind_query(subquery, inner, row, schema),
+    }
+}
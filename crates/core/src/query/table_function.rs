@@ -0,0 +1,214 @@
+use std::rc::Rc;
+
+use super::filter::evaluate_expr;
+use super::operator::{Context, Operator};
+use super::QueryError;
+use crate::types::{
+    Column, Row, Schema, SchemaColumn, TableAlias, TableFunctionCall, TableFunctionName,
+};
+
+// a function that can be used in place of a table in FROM, it takes a list
+// of already evaluated arguments and produces rows
+pub trait TableFunction {
+    // names of the columns in each row
+    fn columns(&self) -> Vec<Column>;
+
+    fn call(
+        &self,
+        args: Vec<serde_json::Value>,
+    ) -> Result<Box<dyn Iterator<Item = Row>>, QueryError>;
+}
+
+// `unnest(array)`, one row per item in the array
+struct Unnest;
+
+impl TableFunction for Unnest {
+    fn columns(&self) -> Vec<Column> {
+        vec!["unnest".into()]
+    }
+
+    fn call(
+        &self,
+        args: Vec<serde_json::Value>,
+    ) -> Result<Box<dyn Iterator<Item = Row>>, QueryError> {
+        let [array] = expect_args(args)?;
+
+        match array {
+            serde_json::Value::Array(items) => Ok(Box::new(
+                items.into_iter().map(|item| Row { items: vec![item] }),
+            )),
+            // unnesting nothing gives you nothing
+            serde_json::Value::Null => Ok(Box::new(std::iter::empty())),
+            _ => Err(QueryError::TypeMismatch {
+                expected: "array".into(),
+            }),
+        }
+    }
+}
+
+// `generate_series(start, stop)`, one row for each integer from start to stop
+struct GenerateSeries;
+
+impl TableFunction for GenerateSeries {
+    fn columns(&self) -> Vec<Column> {
+        vec!["generate_series".into()]
+    }
+
+    fn call(
+        &self,
+        args: Vec<serde_json::Value>,
+    ) -> Result<Box<dyn Iterator<Item = Row>>, QueryError> {
+        let [start, stop] = expect_args(args)?;
+
+        let as_int = |value: serde_json::Value| {
+            value.as_i64().ok_or_else(|| QueryError::TypeMismatch {
+                expected: "i64".into(),
+            })
+        };
+
+        let (start, stop) = (as_int(start)?, as_int(stop)?);
+
+        Ok(Box::new((start..=stop).map(|i| Row {
+            items: vec![i.into()],
+        })))
+    }
+}
+
+fn expect_args<const N: usize>(
+    args: Vec<serde_json::Value>,
+) -> Result<[serde_json::Value; N], QueryError> {
+    let found = args.len();
+    args.try_into()
+        .map_err(|_| QueryError::WrongNumberOfArguments { expected: N, found })
+}
+
+// the columns a table function gives, before they are qualified
+pub fn table_function_columns(function_name: &TableFunctionName) -> Vec<Column> {
+    table_function(function_name).columns()
+}
+
+fn table_function(function_name: &TableFunctionName) -> &'static dyn TableFunction {
+    match function_name {
+        TableFunctionName::Unnest => &Unnest,
+        TableFunctionName::GenerateSeries => &GenerateSeries,
+    }
+}
+
+// rows are produced as they are asked for, so `generate_series(1, 1000000)`
+// under a limit only generates as many as it needs
+pub struct TableFunctionScan<'a> {
+    schema: Schema,
+    rows: Box<dyn Iterator<Item = Row>>,
+    context: Rc<Context<'a>>,
+}
+
+pub fn table_function_scan<'a>(
+    call: &TableFunctionCall,
+    context: &Rc<Context<'a>>,
+) -> Result<TableFunctionScan<'a>, QueryError> {
+    let TableFunctionCall {
+        function_name,
+        args,
+        table_alias,
+    } = call;
+
+    let function = table_function(function_name);
+
+    // arguments can't refer to any columns, so evaluate them against nothing
+    let empty_row = Row { items: vec![] };
+    let empty_schema = Schema::new(vec![]);
+
+    let args = args
+        .iter()
+        .map(|arg| evaluate_expr(&empty_row, &empty_schema, arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // columns are qualified like a table scan, by the function name if
+    // there is no alias
+    let table_alias = table_alias
+        .clone()
+        .unwrap_or_else(|| TableAlias(function_name.to_string().into()));
+
+    let columns = function
+        .columns()
+        .into_iter()
+        .map(|column| {
+            SchemaColumn::Column(Column {
+                table_alias: Some(table_alias.clone()),
+                ..column
+            })
+        })
+        .collect();
+
+    Ok(TableFunctionScan {
+        schema: Schema::new(columns),
+        rows: function.call(args)?,
+        context: context.clone(),
+    })
+}
+
+impl Iterator for TableFunctionScan<'_> {
+    type Item = Result<Row, QueryError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.next()?;
+        self.context.increment_rows_processed();
+        Some(Ok(row))
+    }
+}
+
+impl Operator for TableFunctionScan<'_> {
+    fn schema(&self) -> &Schema {
+        &self.schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GenerateSeries, TableFunction, Unnest};
+    use crate::query::QueryError;
+    use serde_json::json;
+
+    fn call_items(
+        function: &dyn TableFunction,
+        args: Vec<serde_json::Value>,
+    ) -> Result<Vec<Vec<serde_json::Value>>, QueryError> {
+        Ok(function.call(args)?.map(|row| row.items).collect())
+    }
+
+    #[test]
+    fn test_unnest_array() {
+        let items = call_items(&Unnest, vec![json!([1, "two", null])]).unwrap();
+
+        assert_eq!(
+            items,
+            vec![vec![json!(1)], vec![json!("two")], vec![json!(null)]]
+        );
+    }
+
+    #[test]
+    fn test_unnest_null_is_empty() {
+        let items = call_items(&Unnest, vec![json!(null)]).unwrap();
+
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn test_unnest_rejects_non_array() {
+        assert!(matches!(
+            call_items(&Unnest, vec![json!(1)]),
+            Err(QueryError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_generate_series_wrong_number_of_arguments() {
+        assert!(matches!(
+            call_items(&GenerateSeries, vec![json!(1)]),
+            Err(QueryError::WrongNumberOfArguments {
+                expected: 2,
+                found: 1
+            })
+        ));
+    }
+}
@@ -0,0 +1,108 @@
+// TPC-H style `orders` and `lineitem` tables, generated deterministically so
+// planner and executor changes can be measured on something bigger than the
+// static data. keys are named the same on both sides (`orderkey`) so they can
+// be joined, money is in cents and dates are `yyyymmdd` integers
+use serde_json::json;
+
+// orders per unit of scale, lineitems are 1 to 7 per order
+const ORDERS_PER_SCALE: u64 = 1500;
+
+// scale used when the tables are scanned by name
+pub const DEFAULT_SCALE: u64 = 1;
+
+// a handful of queries in the spirit of the TPC-H ones, as (name, sql)
+pub const BENCHMARK_QUERIES: [(&str, &str); 5] = [
+    (
+        "pricing_summary",
+        "select sum(quantity) from lineitem where shipdate <= 19980901",
+    ),
+    (
+        "forecast_revenue",
+        "select sum(extendedprice) from lineitem where discount >= 6",
+    ),
+    (
+        "top_orders",
+        "select orderkey, totalprice from orders order by totalprice desc, orderkey limit 10",
+    ),
+    (
+        "shipping_priority",
+        "select orders.orderkey, orderdate, extendedprice from orders join lineitem on orderkey where shipdate > 19970101 order by extendedprice desc, orders.orderkey limit 10",
+    ),
+    (
+        "large_quantity_lines",
+        "select orders.orderkey, linenumber, quantity from orders join lineitem on orderkey where quantity >= 50 order by orders.orderkey, linenumber limit 10",
+    ),
+];
+
+// small linear congruential generator, good enough for test data and
+// always produces the same sequence for a given seed
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self
+            .state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        self.state >> 33
+    }
+
+    // uniformly pick a number in `low..=high`
+    pub fn between(&mut self, low: u64, high: u64) -> u64 {
+        low + self.next_u64() % (high - low + 1)
+    }
+}
+
+fn date(rng: &mut Rng) -> u64 {
+    let year = rng.between(1992, 1998);
+    let month = rng.between(1, 12);
+    let day = rng.between(1, 28);
+    year * 10_000 + month * 100 + day
+}
+
+pub fn orders(scale: u64) -> Vec<serde_json::Value> {
+    let mut rng = Rng::new(1);
+
+    (1..=scale * ORDERS_PER_SCALE)
+        .map(|orderkey| {
+            let status = ["F", "O", "P"][usize::try_from(rng.between(0, 2)).unwrap()];
+            json!({
+                "orderkey": orderkey,
+                "custkey": rng.between(1, scale * 150),
+                "orderstatus": status,
+                "totalprice": rng.between(1_000, 50_000_000),
+                "orderdate": date(&mut rng),
+            })
+        })
+        .collect()
+}
+
+pub fn lineitem(scale: u64) -> Vec<serde_json::Value> {
+    let mut rng = Rng::new(2);
+    let mut rows = vec![];
+
+    for orderkey in 1..=scale * ORDERS_PER_SCALE {
+        for linenumber in 1..=rng.between(1, 7) {
+            let quantity = rng.between(1, 50);
+            let returnflag = ["A", "N", "R"][usize::try_from(rng.between(0, 2)).unwrap()];
+            rows.push(json!({
+                "orderkey": orderkey,
+                "linenumber": linenumber,
+                "partkey": rng.between(1, scale * 200),
+                "quantity": quantity,
+                "extendedprice": quantity * rng.between(90_000, 200_000),
+                "discount": rng.between(0, 10),
+                "returnflag": returnflag,
+                "shipdate": date(&mut rng),
+            }));
+        }
+    }
+
+    rows
+}
@@ -1,8 +1,10 @@
-use std::{fmt::Display, hash::Hash};
+use std::{collections::HashMap, fmt::Display, hash::Hash, sync::Arc, time::Duration};
 
 #[derive(Debug, PartialOrd, PartialEq, Eq, Ord, Hash, Clone)]
 pub struct Column {
-    pub name: String,
+    // names are shared rather than copied, since the same few are cloned
+    // into every schema that passes through a plan
+    pub name: Arc<str>,
     pub table_alias: Option<TableAlias>,
 }
 
@@ -22,13 +24,13 @@ impl Display for Column {
 impl std::convert::From<&str> for Column {
     fn from(name: &str) -> Column {
         Column {
-            name: name.to_string(),
+            name: name.into(),
             table_alias: None,
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Column {
         column: Column,
@@ -44,15 +46,113 @@ pub enum Expr {
     Nested {
         expr: Box<Expr>,
     },
+    Not {
+        expr: Box<Expr>,
+    },
     FunctionCall {
         function_name: FunctionName,
         args: Vec<Expr>,
+        // `count(distinct x)`, only use each value once
+        distinct: bool,
+    },
+    // `ARRAY[1, 2, 3]`
+    Array {
+        items: Vec<Expr>,
+    },
+    // `tags[1]`, arrays are indexed from 1 like postgres
+    Index {
+        expr: Box<Expr>,
+        index: Box<Expr>,
     },
+    // `x in (1, 2, 3)` or `(a, b) not in ((1, 2), (3, 4))`
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    // `*` or `artist.*` alongside other fields, replaced with the matching
+    // columns of the input when the fields are projected
+    Wildcard {
+        table_alias: Option<TableAlias>,
+    },
+    // `cast(x as text)` or `x::text`
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    // `x is null` or `x is not null`
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    // `name like 'A%'`, `_` matches one character and `%` any number
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+        case_insensitive: bool,
+        escape: Option<char>,
+    },
+    // `x > ANY (array)`, compare against every item in an array
+    Quantified {
+        left: Box<Expr>,
+        op: Op,
+        quantifier: Quantifier,
+        right: Box<Expr>,
+    },
+    // `x > all (select y from t)`, only at the top of a where clause, where
+    // the subquery is run once and each row compared against its values
+    QuantifiedSubquery {
+        left: Box<Expr>,
+        op: Op,
+        quantifier: Quantifier,
+        subquery: Box<Query>,
+    },
+    // `x in (select y from t)`, only at the top of a where clause, where
+    // it is run as a semi join
+    InSubquery {
+        expr: Box<Expr>,
+        subquery: Box<Query>,
+        negated: bool,
+    },
+    // `exists (select ...)`, which can refer to columns of the outer row.
+    // only at the top of a where clause
+    Exists {
+        subquery: Box<Query>,
+        negated: bool,
+    },
+    // `Title as album_title`, only in the projection
+    Alias {
+        expr: Box<Expr>,
+        alias: String,
+    },
+    // `$1`, numbered from 1 and replaced with a value by
+    // `parse_with_parameters` before the query is run
+    Parameter {
+        index: usize,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Quantifier {
+    Any,
+    All,
+}
+
+impl Display for Quantifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Quantifier::Any => "any",
+            Quantifier::All => "all",
+        };
+        write!(f, "{str}")
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum FunctionName {
     Aggregate(AggregateFunctionName),
+    Scalar(ScalarFunctionName),
 }
 
 impl Display for FunctionName {
@@ -61,51 +161,152 @@ impl Display for FunctionName {
             FunctionName::Aggregate(aggregate_function_name) => {
                 write!(f, "{aggregate_function_name}")
             }
+            FunctionName::Scalar(scalar_function_name) => write!(f, "{scalar_function_name}"),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+// the types a value can be cast to
+#[derive(Debug, PartialEq, Clone)]
+pub enum DataType {
+    Integer,
+    Float,
+    Text,
+    Boolean,
+    Date,
+    Timestamp,
+    Interval,
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            DataType::Integer => "integer",
+            DataType::Float => "float",
+            DataType::Text => "text",
+            DataType::Boolean => "boolean",
+            DataType::Date => "date",
+            DataType::Timestamp => "timestamp",
+            DataType::Interval => "interval",
+        };
+        write!(f, "{str}")
+    }
+}
+
+// functions worked out from the values in a single row
+#[derive(Debug, PartialEq, Clone)]
+pub enum ScalarFunctionName {
+    Upper,
+    Lower,
+    Length,
+    Substr,
+    Abs,
+    Round,
+    Floor,
+    Ceil,
+    Mod,
+    Now,
+    DatePart,
+    Strftime,
+}
+
+impl Display for ScalarFunctionName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            ScalarFunctionName::Upper => "upper",
+            ScalarFunctionName::Lower => "lower",
+            ScalarFunctionName::Length => "length",
+            ScalarFunctionName::Substr => "substr",
+            ScalarFunctionName::Abs => "abs",
+            ScalarFunctionName::Round => "round",
+            ScalarFunctionName::Floor => "floor",
+            ScalarFunctionName::Ceil => "ceil",
+            ScalarFunctionName::Mod => "mod",
+            ScalarFunctionName::Now => "now",
+            ScalarFunctionName::DatePart => "date_part",
+            ScalarFunctionName::Strftime => "strftime",
+        };
+        write!(f, "{str}")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum AggregateFunctionName {
     Sum,
+    Count,
+    Avg,
+    Min,
+    Max,
+    ArrayAgg,
+    StringAgg,
+    Variance,
+    Stddev,
+    PercentileCont,
 }
 
 impl Display for AggregateFunctionName {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             AggregateFunctionName::Sum => "sum",
+            AggregateFunctionName::Count => "count",
+            AggregateFunctionName::Avg => "avg",
+            AggregateFunctionName::Min => "min",
+            AggregateFunctionName::Max => "max",
+            AggregateFunctionName::ArrayAgg => "array_agg",
+            AggregateFunctionName::StringAgg => "string_agg",
+            AggregateFunctionName::Variance => "variance",
+            AggregateFunctionName::Stddev => "stddev",
+            AggregateFunctionName::PercentileCont => "percentile_cont",
         };
         write!(f, "{str}")
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Op {
     Equals,
+    NotEquals,
     GreaterThan,
     GreaterThanOrEqual,
     LessThan,
     LessThanOrEqual,
     Add,
     Subtract,
+    Multiply,
+    JsonGet,
+    JsonGetText,
+    Contains,
+    ContainedBy,
+    And,
+    Or,
+    Concat,
 }
 
 impl Display for Op {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let str = match self {
             Op::Equals => "equals",
+            Op::NotEquals => "not_equals",
             Op::GreaterThan => "greater_than",
             Op::GreaterThanOrEqual => "greater_than_or_equal",
             Op::LessThan => "less_than",
             Op::LessThanOrEqual => "less_than_or_equal",
             Op::Add => "add",
             Op::Subtract => "subtract",
+            Op::Multiply => "multiply",
+            Op::JsonGet => "json_get",
+            Op::JsonGetText => "json_get_text",
+            Op::Contains => "contains",
+            Op::ContainedBy => "contained_by",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Concat => "concat",
         };
         write!(f, "{str}")
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Join {
     #[allow(clippy::struct_field_names)]
     pub join_type: JoinType,
@@ -114,17 +315,42 @@ pub struct Join {
     pub on: JoinOn,
 }
 
-#[derive(Debug, PartialEq)]
+// every left row paired with every right row, from `from a, b`
+#[derive(Debug, PartialEq, Clone)]
+pub struct CrossJoin {
+    pub left_from: Box<Query>,
+    pub right_from: Box<Query>,
+}
+
+// a join on anything other than one column equalling another, like
+// `on Track.Milliseconds > Album.AlbumId * 1000`, which can't be hashed so
+// every pair of rows is checked
+#[derive(Debug, PartialEq, Clone)]
+pub struct NestedLoopJoin {
+    #[allow(clippy::struct_field_names)]
+    pub join_type: JoinType,
+    pub left_from: Box<Query>,
+    pub right_from: Box<Query>,
+    pub on: Expr,
+}
+
+// `describe Album`, one row per column of a table
+#[derive(Debug, PartialEq, Clone)]
+pub struct Describe {
+    pub table_name: TableName,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct JoinOn {
     pub left: Column,
     pub right: Column,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct TableName(pub String);
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
-pub struct TableAlias(pub String);
+pub struct TableAlias(pub Arc<str>);
 
 impl Display for TableAlias {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
@@ -132,60 +358,140 @@ impl Display for TableAlias {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct From {
     pub table_name: TableName,
     pub table_alias: Option<TableAlias>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct TableFunctionCall {
+    pub function_name: TableFunctionName,
+    pub args: Vec<Expr>,
+    pub table_alias: Option<TableAlias>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TableFunctionName {
+    Unnest,
+    GenerateSeries,
+}
+
+impl Display for TableFunctionName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            TableFunctionName::Unnest => "unnest",
+            TableFunctionName::GenerateSeries => "generate_series",
+        };
+        write!(f, "{str}")
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Filter {
     pub from: Box<Query>,
     pub filter: Expr,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Project {
     pub from: Box<Query>,
     pub fields: Vec<Expr>,
 }
 
-#[derive(Debug, PartialEq)]
+// rows with the same values for `group_by` are collected together, groups
+// that don't match `having` are dropped, and `fields` are worked out once for
+// each group that is left
+#[derive(Debug, PartialEq, Clone)]
+pub struct GroupBy {
+    pub from: Box<Query>,
+    #[allow(clippy::struct_field_names)]
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub fields: Vec<Expr>,
+}
+
+// every row of `left` followed by every row of `right`, from `union all`.
+// plain `union` is a `Distinct` of this
+#[derive(Debug, PartialEq, Clone)]
+pub struct Union {
+    pub left: Box<Query>,
+    pub right: Box<Query>,
+}
+
+// `Track tablesample bernoulli (10) repeatable (42)`, each row is kept with a
+// `percent` chance. the same seed keeps the same rows every time
+#[derive(Debug, PartialEq, Clone)]
+pub struct Sample {
+    pub from: Box<Query>,
+    pub percent: f64,
+    pub seed: Option<u64>,
+}
+
+// `select distinct`, the first of each set of identical rows
+#[derive(Debug, PartialEq, Clone)]
+pub struct Distinct {
+    pub from: Box<Query>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum JoinType {
     Inner,
     LeftOuter,
 }
 
-#[derive(Debug, PartialEq)]
+// `limit 10 offset 20`, either of which can be left out. they can be any
+// expression without columns, like `5 + 5` or `$1`, which is worked out when
+// the query runs
+#[derive(Debug, PartialEq, Clone)]
 pub struct Limit {
     pub from: Box<Query>,
-    pub limit: u64,
+    #[allow(clippy::struct_field_names)]
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct OrderBy {
     pub from: Box<Query>,
     pub order_by_exprs: Vec<OrderByExpr>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct OrderByExpr {
     pub column: Column,
     pub order: Order,
 }
 
-#[derive(Debug, PartialEq)]
+// `explain select ...`, the plan that would run rather than its result
+#[derive(Debug, PartialEq, Clone)]
+pub struct Explain {
+    pub query: Box<Query>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Order {
     Asc,
     Desc,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Query {
     From(From),
+    TableFunction(TableFunctionCall),
     Filter(Filter),
+    Sample(Sample),
     Join(Join),
+    CrossJoin(CrossJoin),
+    NestedLoopJoin(NestedLoopJoin),
+    Describe(Describe),
+    // `show tables`, one row per table
+    ShowTables,
+    Explain(Explain),
     Project(Project),
+    GroupBy(GroupBy),
+    Distinct(Distinct),
+    Union(Union),
     Limit(Limit),
     OrderBy(OrderBy),
 }
@@ -202,7 +508,7 @@ impl Row {
         self.items.get(index)
     }
 
-    pub fn get_named(&self, named: &String, schema: &Schema) -> Option<&serde_json::Value> {
+    pub fn get_named(&self, named: &str, schema: &Schema) -> Option<&serde_json::Value> {
         let index = schema.get_index_for_named(named)?;
 
         self.items.get(index)
@@ -211,11 +517,32 @@ impl Row {
     pub fn extend(&mut self, row: Row) {
         self.items.extend(row.items);
     }
+
+    // rough number of bytes used by the values in this row
+    pub fn estimated_size(&self) -> usize {
+        self.items.iter().map(estimated_value_size).sum()
+    }
+}
+
+fn estimated_value_size(value: &serde_json::Value) -> usize {
+    let inner = match value {
+        serde_json::Value::String(string) => string.len(),
+        serde_json::Value::Array(items) => items.iter().map(estimated_value_size).sum(),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| key.len() + estimated_value_size(value))
+            .sum(),
+        serde_json::Value::Null | serde_json::Value::Bool(_) | serde_json::Value::Number(_) => 0,
+    };
+    std::mem::size_of::<serde_json::Value>() + inner
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Schema {
-    pub columns: Vec<SchemaColumn>,
+    columns: Vec<SchemaColumn>,
+    // positions of the columns with each name, so finding a column for every
+    // cell of every row doesn't mean scanning the whole schema each time
+    positions: HashMap<Arc<str>, Vec<usize>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -236,37 +563,123 @@ impl Display for SchemaColumn {
 }
 
 impl Schema {
+    pub fn new(columns: Vec<SchemaColumn>) -> Self {
+        let mut positions: HashMap<Arc<str>, Vec<usize>> = HashMap::new();
+
+        for (index, schema_column) in columns.iter().enumerate() {
+            let name = match schema_column {
+                SchemaColumn::Column(column) => column.name.clone(),
+                SchemaColumn::Named(name) => name.as_str().into(),
+            };
+
+            positions.entry(name).or_default().push(index);
+        }
+
+        Schema { columns, positions }
+    }
+
+    pub fn columns(&self) -> &[SchemaColumn] {
+        &self.columns
+    }
+
+    // a qualified column (`Album.ArtistId`) must match name and table alias
+    // exactly, an unqualified one (`ArtistId`) matches the first column with
+    // that name, so in a join the leftmost table wins
     pub fn get_index_for_column(&self, column: &Column) -> Option<usize> {
-        self.columns
+        self.positions
+            .get(&column.name)?
             .iter()
-            .enumerate()
-            .find(|(_, schema_column)| match schema_column {
-                SchemaColumn::Column(column_name) => column_name == column,
+            .copied()
+            .find(|index| match &self.columns[*index] {
+                SchemaColumn::Column(column_name) => match &column.table_alias {
+                    Some(_) => column_name.table_alias == column.table_alias,
+                    None => true,
+                },
                 SchemaColumn::Named(_) => false,
             })
-            .map(|(i, _)| i)
     }
 
-    pub fn get_index_for_named(&self, named: &String) -> Option<usize> {
-        self.columns
+    pub fn get_index_for_named(&self, named: &str) -> Option<usize> {
+        self.positions
+            .get(named)?
             .iter()
-            .enumerate()
-            .find(|(_, schema_column)| match schema_column {
-                SchemaColumn::Named(name) => name == named,
-                SchemaColumn::Column(_) => false,
-            })
-            .map(|(i, _)| i)
+            .copied()
+            .find(|index| matches!(self.columns[*index], SchemaColumn::Named(_)))
     }
 
     pub fn extend(&mut self, schema: Schema) {
+        let offset = self.columns.len();
+
+        for (name, indexes) in schema.positions {
+            self.positions
+                .entry(name)
+                .or_default()
+                .extend(indexes.into_iter().map(|index| index + offset));
+        }
+
         self.columns.extend(schema.columns);
     }
+
+    // names used for output, columns are written without their table alias
+    // unless another column shares the same name, in which case they are
+    // written in full (`Album.ArtistId`, `Artist.ArtistId`). names that are
+    // still the same after that, like two `count`s or the same column twice,
+    // get their position in the row added (`count_1`, `count_2`)
+    pub fn output_names(&self) -> Vec<String> {
+        fn counts<'a>(names: impl Iterator<Item = &'a str>) -> HashMap<&'a str, usize> {
+            let mut counts = HashMap::new();
+            for name in names {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+            counts
+        }
+
+        let bare_counts = counts(self.columns.iter().map(SchemaColumn::bare_name));
+
+        let names = self
+            .columns
+            .iter()
+            .map(|schema_column| {
+                let bare_name = schema_column.bare_name();
+                if bare_counts[bare_name] > 1 {
+                    schema_column.to_string()
+                } else {
+                    bare_name.to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let name_counts = counts(names.iter().map(String::as_str));
+
+        names
+            .iter()
+            .zip(&self.columns)
+            .enumerate()
+            .map(|(index, (name, schema_column))| {
+                if name_counts[name.as_str()] > 1 {
+                    format!("{}_{}", schema_column.bare_name(), index + 1)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect()
+    }
+}
+
+impl SchemaColumn {
+    pub fn bare_name(&self) -> &str {
+        match self {
+            SchemaColumn::Column(column) => &column.name,
+            SchemaColumn::Named(name) => name,
+        }
+    }
 }
 
 pub struct QueryStep {
     pub schema: Schema,
     pub rows: Vec<Row>,
     pub cost: Cost,
+    pub profile: Profile,
 }
 
 impl QueryStep {
@@ -274,26 +687,69 @@ impl QueryStep {
     pub fn to_json(&self) -> serde_json::Value {
         let mut output_rows = vec![];
 
+        let output_names = self.schema.output_names();
+
         for row in &self.rows {
             let mut output_row = serde_json::Map::new();
-            for column in &self.schema.columns {
-                let value = match column {
-                    SchemaColumn::Column(column_name) => {
-                        row.get_column(column_name, &self.schema).unwrap()
-                    }
-                    SchemaColumn::Named(name) => row.get_named(name, &self.schema).unwrap(),
-                };
-                output_row.insert(column.to_string(), value.clone());
+            for (name, value) in output_names.iter().zip(&row.items) {
+                output_row.insert(name.clone(), value.clone());
             }
             output_rows.push(serde_json::Value::Object(output_row));
         }
         serde_json::Value::Array(output_rows)
     }
+
+    // a header line of output names then one line per row. strings are
+    // written bare, nulls as empty fields and arrays or objects as json
+    pub fn write_csv(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        let header = self.schema.output_names();
+        write_csv_line(writer, header.iter().map(String::as_str))?;
+
+        for row in &self.rows {
+            let fields = row
+                .items
+                .iter()
+                .map(|value| match value {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(string) => string.clone(),
+                    value => value.to_string(),
+                })
+                .collect::<Vec<_>>();
+
+            write_csv_line(writer, fields.iter().map(String::as_str))?;
+        }
+        Ok(())
+    }
+}
+
+fn write_csv_line<'a>(
+    writer: &mut impl std::io::Write,
+    fields: impl Iterator<Item = &'a str>,
+) -> std::io::Result<()> {
+    for (index, field) in fields.enumerate() {
+        if index > 0 {
+            write!(writer, ",")?;
+        }
+        // quote anything that would otherwise be split into more fields or
+        // lines, doubling any quotes inside
+        if field.contains([',', '"', '\n', '\r']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            write!(writer, "{field}")?;
+        }
+    }
+    writeln!(writer)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Cost {
     pub rows_processed: u64,
+    // rows written to disk by joins that didn't fit in memory, and how big
+    // they were
+    pub spilled_rows: u64,
+    pub spilled_bytes: u64,
+    // rows a join's bloom filter dropped while they were being scanned
+    pub bloom_filtered_rows: u64,
 }
 
 impl Default for Cost {
@@ -304,14 +760,64 @@ impl Default for Cost {
 
 impl Cost {
     pub fn new() -> Self {
-        Cost { rows_processed: 0 }
+        Cost {
+            rows_processed: 0,
+            spilled_rows: 0,
+            spilled_bytes: 0,
+            bloom_filtered_rows: 0,
+        }
     }
 
     pub fn increment_rows_processed(&mut self) {
         self.rows_processed += 1;
     }
 
+    pub fn add_rows_processed(&mut self, rows: usize) {
+        self.rows_processed += rows as u64;
+    }
+
     pub fn extend(&mut self, cost: &Cost) {
         self.rows_processed += cost.rows_processed;
+        self.spilled_rows += cost.spilled_rows;
+        self.spilled_bytes += cost.spilled_bytes;
+        self.bloom_filtered_rows += cost.bloom_filtered_rows;
+    }
+}
+
+// statistics for a single operator in the plan, along with the operators
+// that fed it
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub operator: String,
+    pub rows_in: usize,
+    pub rows_out: usize,
+    pub elapsed: Duration,
+    pub memory_bytes: usize,
+    pub children: Vec<Profile>,
+}
+
+impl Profile {
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter, depth: usize) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} (rows_in={}, rows_out={}, time={:?}, memory={}B)",
+            "",
+            self.operator,
+            self.rows_in,
+            self.rows_out,
+            self.elapsed,
+            self.memory_bytes,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
     }
 }
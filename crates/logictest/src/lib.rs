@@ -0,0 +1,308 @@
+// runs sqllogictest style files against the engine
+//
+// supported records:
+//
+//   statement ok
+//   <sql>
+//
+//   statement error
+//   <sql>
+//
+//   query <column types> [nosort|rowsort]
+//   <sql>
+//   ----
+//   <one line per row, values separated by a single space>
+//
+// records are separated by blank lines, lines starting with `#` are comments
+use core::{parse, run_query, QueryStep};
+
+#[derive(Debug, PartialEq)]
+pub enum SortMode {
+    NoSort,
+    RowSort,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Record {
+    StatementOk {
+        line: usize,
+        sql: String,
+    },
+    StatementError {
+        line: usize,
+        sql: String,
+    },
+    Query {
+        line: usize,
+        column_types: String,
+        sort_mode: SortMode,
+        sql: String,
+        expected: Vec<String>,
+    },
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    UnknownRecord { line: usize, found: String },
+    UnknownSortMode { line: usize, found: String },
+    MissingColumnTypes { line: usize },
+    MissingSql { line: usize },
+}
+
+#[derive(Debug)]
+pub enum Failure {
+    Parse(ParseError),
+    StatementFailed {
+        line: usize,
+        error: String,
+    },
+    StatementSucceeded {
+        line: usize,
+    },
+    ColumnCountMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    ResultMismatch {
+        line: usize,
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+}
+
+impl std::convert::From<ParseError> for Failure {
+    fn from(parse_error: ParseError) -> Failure {
+        Failure::Parse(parse_error)
+    }
+}
+
+pub fn parse_records(input: &str) -> Result<Vec<Record>, ParseError> {
+    let mut lines = input.lines().enumerate().peekable();
+    let mut records = vec![];
+
+    while let Some((index, line)) = lines.next() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (Some("statement"), Some(expect)) => {
+                let sql = collect_sql(&mut lines, line_number)?;
+                match expect {
+                    "ok" => records.push(Record::StatementOk {
+                        line: line_number,
+                        sql,
+                    }),
+                    "error" => records.push(Record::StatementError {
+                        line: line_number,
+                        sql,
+                    }),
+                    _ => {
+                        return Err(ParseError::UnknownRecord {
+                            line: line_number,
+                            found: line.to_string(),
+                        })
+                    }
+                }
+            }
+            (Some("query"), column_types) => {
+                let column_types = column_types
+                    .ok_or(ParseError::MissingColumnTypes { line: line_number })?
+                    .to_string();
+
+                let sort_mode = match words.next() {
+                    None | Some("nosort") => SortMode::NoSort,
+                    Some("rowsort") => SortMode::RowSort,
+                    Some(other) => {
+                        return Err(ParseError::UnknownSortMode {
+                            line: line_number,
+                            found: other.to_string(),
+                        })
+                    }
+                };
+
+                let sql = collect_sql(&mut lines, line_number)?;
+
+                // results run until the next blank line
+                let mut expected = vec![];
+                while let Some((_, result_line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+                    expected.push(result_line.trim_end().to_string());
+                }
+
+                records.push(Record::Query {
+                    line: line_number,
+                    column_types,
+                    sort_mode,
+                    sql,
+                    expected,
+                });
+            }
+            _ => {
+                return Err(ParseError::UnknownRecord {
+                    line: line_number,
+                    found: line.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(records)
+}
+
+// sql runs until a blank line or the `----` results separator
+fn collect_sql<'a>(
+    lines: &mut std::iter::Peekable<impl Iterator<Item = (usize, &'a str)>>,
+    line: usize,
+) -> Result<String, ParseError> {
+    let mut sql_lines = vec![];
+
+    while let Some((_, sql_line)) = lines.next_if(|(_, l)| !l.trim().is_empty()) {
+        if sql_line.trim() == "----" {
+            break;
+        }
+        sql_lines.push(sql_line);
+    }
+
+    if sql_lines.is_empty() {
+        Err(ParseError::MissingSql { line })
+    } else {
+        Ok(sql_lines.join("\n"))
+    }
+}
+
+fn execute(sql: &str) -> Result<QueryStep, String> {
+    let query = parse(sql).map_err(|e| format!("{e:?}"))?;
+    run_query(&query).map_err(|e| format!("{e:?}"))
+}
+
+fn format_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "NULL".to_string(),
+        serde_json::Value::String(s) if s.is_empty() => "(empty)".to_string(),
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn format_rows(query_step: &QueryStep) -> Vec<String> {
+    query_step
+        .rows
+        .iter()
+        .map(|row| {
+            row.items
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+pub fn run_record(record: &Record) -> Result<(), Failure> {
+    match record {
+        Record::StatementOk { line, sql } => match execute(sql) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(Failure::StatementFailed { line: *line, error }),
+        },
+        Record::StatementError { line, sql } => match execute(sql) {
+            Ok(_) => Err(Failure::StatementSucceeded { line: *line }),
+            Err(_) => Ok(()),
+        },
+        Record::Query {
+            line,
+            column_types,
+            sort_mode,
+            sql,
+            expected,
+        } => {
+            let query_step =
+                execute(sql).map_err(|error| Failure::StatementFailed { line: *line, error })?;
+
+            let column_count = query_step.schema.columns().len();
+            if column_types.len() != column_count {
+                return Err(Failure::ColumnCountMismatch {
+                    line: *line,
+                    expected: column_types.len(),
+                    found: column_count,
+                });
+            }
+
+            let mut found = format_rows(&query_step);
+            let mut expected = expected.clone();
+
+            if *sort_mode == SortMode::RowSort {
+                found.sort();
+                expected.sort();
+            }
+
+            if found == expected {
+                Ok(())
+            } else {
+                Err(Failure::ResultMismatch {
+                    line: *line,
+                    expected,
+                    found,
+                })
+            }
+        }
+    }
+}
+
+// run every record, collecting all failures rather than stopping at the first
+pub fn run_script(input: &str) -> Result<(), Vec<Failure>> {
+    let records = parse_records(input).map_err(|e| vec![Failure::from(e)])?;
+
+    let failures: Vec<Failure> = records
+        .iter()
+        .filter_map(|record| run_record(record).err())
+        .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_records, Record, SortMode};
+
+    #[test]
+    fn test_parse_records() {
+        let input = r"
+# a comment
+statement ok
+select * from animal
+
+query IT rowsort
+select animal_id, animal_name
+from animal
+----
+1 horse
+2 dog
+";
+
+        let expected = vec![
+            Record::StatementOk {
+                line: 3,
+                sql: "select * from animal".to_string(),
+            },
+            Record::Query {
+                line: 6,
+                column_types: "IT".to_string(),
+                sort_mode: SortMode::RowSort,
+                sql: "select animal_id, animal_name\nfrom animal".to_string(),
+                expected: vec!["1 horse".to_string(), "2 dog".to_string()],
+            },
+        ];
+
+        assert_eq!(parse_records(input).unwrap(), expected);
+    }
+}
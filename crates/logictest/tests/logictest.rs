@@ -0,0 +1,29 @@
+use std::path::Path;
+
+// runs every `.slt` file in `test_files`
+#[test]
+fn test_logictest_files() {
+    let test_files = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_files");
+
+    let mut paths: Vec<_> = std::fs::read_dir(test_files)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "slt"))
+        .collect();
+    paths.sort();
+
+    assert!(!paths.is_empty(), "no .slt files found");
+
+    let mut failed = vec![];
+
+    for path in paths {
+        let input = std::fs::read_to_string(&path).unwrap();
+        if let Err(failures) = logictest::run_script(&input) {
+            for failure in failures {
+                failed.push(format!("{}: {failure:?}", path.display()));
+            }
+        }
+    }
+
+    assert!(failed.is_empty(), "{}", failed.join("\n"));
+}